@@ -0,0 +1,361 @@
+//! Frame advantage of attacks that hit or were blocked.
+//!
+//! After an attack connects, the defender goes into hit or block stun, and
+//! from then on each player is free to act again on the first frame their
+//! recovery runs out. The difference between the two is the
+//! frame advantage of the attacker: positive when they recover first, and
+//! punishable when the defender recovers enough frames earlier.
+//!
+//! Interactions where the defender ends up on the ground are left out,
+//! since getting up has no fixed length. So are all interactions of captures
+//! taken without the move offsets, where nobody ever appears to be in stun.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::globals::{Character, Player};
+use crate::replay::Replay;
+use crate::states::events::{self, EventKind};
+use crate::states::game_state::RoundState;
+
+/// Frame advantage of a single attack that connected
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub round: u8,
+    /// Round frame the attack connected on
+    pub frame: u128,
+    pub attacker: Player,
+    pub character: Character,
+    pub move_id: u32,
+    /// Hit, counter hit or block
+    pub outcome: EventKind,
+    /// Frames the attacker recovers before the defender
+    pub advantage: i64,
+}
+
+/// Frame advantage measured over every sample of a move
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FrameAdvantage {
+    /// Most common value, which ignores the odd sample thrown off by
+    /// frames missing from the capture
+    pub typical: i64,
+    pub min: i64,
+    pub max: i64,
+    pub samples: usize,
+}
+
+/// Frame data of a single move
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameDataRow {
+    pub character: Character,
+    pub move_id: u32,
+    pub on_block: Option<FrameAdvantage>,
+    pub on_hit: Option<FrameAdvantage>,
+    pub on_counter_hit: Option<FrameAdvantage>,
+}
+
+/// Frame advantage samples of every move seen, per character
+#[derive(Default)]
+pub struct FrameDataTable {
+    moves: BTreeMap<(u8, u32), Samples>,
+}
+
+struct Samples {
+    character: Character,
+    on_block: Vec<i64>,
+    on_hit: Vec<i64>,
+    on_counter_hit: Vec<i64>,
+}
+
+/// Frame advantage of every hit and block over consecutive frames of a round
+pub fn interactions(states: &[RoundState]) -> Vec<Interaction> {
+    events::extract(states)
+        .into_iter()
+        .filter(|event| matches!(event.kind, EventKind::Hit | EventKind::CounterHit | EventKind::Block))
+        .filter_map(|event| {
+            let start = states.iter().position(|state| state.get_round_frame() == event.frame)?;
+            let advantage = advantage(&states[start.checked_sub(1)?..], event.attacker)?;
+
+            Some(Interaction {
+                round: event.round,
+                frame: event.frame,
+                attacker: event.attacker,
+                character: states[start].get_player_state(event.attacker).character(),
+                move_id: event.move_id,
+                outcome: event.kind,
+                advantage,
+            })
+        })
+        .collect()
+}
+
+/// Frames `attacker` recovers before the opponent, where the first of
+/// `states` is the frame before the attack connected. Counting starts once
+/// the opponent goes into stun, since on the contact frame neither player's
+/// recovery reflects the hit yet. `None` if the opponent never goes into
+/// stun, if either player doesn't recover before the round ends or if the
+/// opponent is knocked down first.
+fn advantage(states: &[RoundState], attacker: Player) -> Option<i64> {
+    let before = states.first()?.get_player_state(!attacker);
+    let stunned = states.iter().skip(1).position(|state| {
+        let defender = state.get_player_state(!attacker);
+        defender.move_id() != before.move_id()
+            || (before.recovery_remaining() == 0 && defender.recovery_remaining() > 0)
+    })?;
+
+    let mut attacker_free = None;
+    let mut defender_free = None;
+
+    for state in &states[stunned + 1..] {
+        let defender = state.get_player_state(!attacker);
+        if defender_free.is_none() && defender.ground_state().is_some() {
+            return None;
+        }
+
+        if attacker_free.is_none() && state.get_player_state(attacker).recovery_remaining() == 0 {
+            attacker_free = Some(state.get_round_frame());
+        }
+        if defender_free.is_none() && defender.recovery_remaining() == 0 {
+            defender_free = Some(state.get_round_frame());
+        }
+
+        if let (Some(attacker_free), Some(defender_free)) = (attacker_free, defender_free) {
+            return Some(defender_free as i64 - attacker_free as i64);
+        }
+    }
+
+    None
+}
+
+impl FrameAdvantage {
+    /// Summarizes `samples`, `None` when there are none
+    pub fn from_samples(samples: &[i64]) -> Option<Self> {
+        let mut counts: BTreeMap<i64, usize> = BTreeMap::new();
+        for sample in samples {
+            *counts.entry(*sample).or_insert(0) += 1;
+        }
+
+        // Ties go to the lowest value, the safer assumption when punishing
+        let typical = counts
+            .iter()
+            .rev()
+            .max_by_key(|(_, count)| **count)
+            .map(|(value, _)| *value)?;
+
+        Some(Self {
+            typical,
+            min: *counts.keys().next()?,
+            max: *counts.keys().next_back()?,
+            samples: samples.len(),
+        })
+    }
+}
+
+impl FrameDataTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, interaction: &Interaction) {
+        let samples = self
+            .moves
+            .entry((interaction.character as u8, interaction.move_id))
+            .or_insert_with(|| Samples {
+                character: interaction.character,
+                on_block: Vec::new(),
+                on_hit: Vec::new(),
+                on_counter_hit: Vec::new(),
+            });
+
+        match interaction.outcome {
+            EventKind::Block => samples.on_block.push(interaction.advantage),
+            EventKind::CounterHit => samples.on_counter_hit.push(interaction.advantage),
+            _ => samples.on_hit.push(interaction.advantage),
+        }
+    }
+
+    /// Adds every interaction of every round in `replay`, returning how
+    /// many were added
+    pub fn add_replay(&mut self, replay: &Replay) -> usize {
+        let mut added = 0;
+        for round in replay.rounds() {
+            for interaction in interactions(&round.states) {
+                self.add(&interaction);
+                added += 1;
+            }
+        }
+
+        added
+    }
+
+    /// Every move, ordered by character and move ID
+    pub fn rows(&self) -> Vec<FrameDataRow> {
+        self.moves
+            .iter()
+            .map(|((_, move_id), samples)| FrameDataRow {
+                character: samples.character,
+                move_id: *move_id,
+                on_block: FrameAdvantage::from_samples(&samples.on_block),
+                on_hit: FrameAdvantage::from_samples(&samples.on_hit),
+                on_counter_hit: FrameAdvantage::from_samples(&samples.on_counter_hit),
+            })
+            .collect()
+    }
+
+    /// Writes one line per move with the typical advantage and the number
+    /// of samples of each outcome
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> Result<(), Box<dyn Error>> {
+        let column = |advantage: Option<FrameAdvantage>| match advantage {
+            Some(advantage) => format!("{:+},{}", advantage.typical, advantage.samples),
+            None => ",0".to_string(),
+        };
+
+        writeln!(w, "character,move_id,on_block,block_samples,on_hit,hit_samples,on_counter_hit,counter_hit_samples")?;
+        for row in self.rows() {
+            writeln!(
+                w,
+                "{:?},{},{},{},{}",
+                row.character,
+                row.move_id,
+                column(row.on_block),
+                column(row.on_hit),
+                column(row.on_counter_hit)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Saves the table as JSON if `path` ends in `.json` and as CSV otherwise
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::to_writer_pretty(&mut writer, &self.rows())?,
+            _ => self.write_csv(&mut writer)?,
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::states::player_state::{PlayerInfo, PlayerState};
+
+    const JAB: u32 = 100;
+    const LAUNCHER: u32 = 200;
+    const HITSTUN: u32 = 300;
+    const BLOCKSTUN: u32 = 301;
+
+    /// Player one's jab, active on frames 1 to 3 and recovered on frame 11,
+    /// with `defender` setting up player two on each of frames 0 to 19
+    fn jab(defender: impl Fn(u32, &mut PlayerState)) -> Vec<RoundState> {
+        (0..20)
+            .map(|frame| {
+                let mut one = PlayerState::blank(Player::One, Character::NotSelected);
+                one.move_id = JAB;
+                one.move_frame = 9 + frame;
+                one.recovery_remaining = 11u32.saturating_sub(frame);
+                one.attack_flags = 1;
+                one.active_start = 10;
+                one.active_end = 12;
+
+                let mut two = PlayerState::blank(Player::Two, Character::NotSelected);
+                defender(frame, &mut two);
+
+                let info = || PlayerInfo { screen_name: None };
+                RoundState::new(1, frame.into(), (info(), info()), (one, two))
+            })
+            .collect()
+    }
+
+    /// Puts the player into `stun` from frame 2 on, free again on frame `free`
+    fn stun(player: &mut PlayerState, frame: u32, stun: u32, free: u32) {
+        if frame >= 2 {
+            player.move_id = stun;
+            player.move_frame = frame - 2;
+            player.recovery_remaining = free.saturating_sub(frame);
+        }
+    }
+
+    /// Only the interaction of player one's jab
+    fn jab_interaction(states: &[RoundState]) -> Interaction {
+        let interactions: Vec<_> = interactions(states).into_iter().filter(|i| i.attacker == Player::One).collect();
+        assert_eq!(interactions.len(), 1);
+        interactions[0]
+    }
+
+    #[test]
+    fn counts_from_the_start_of_stun() {
+        // Damage shows up on frame 1, the hitstun only on frame 2
+        let states = jab(|frame, two| {
+            if frame >= 1 {
+                two.damage_received = 10;
+            }
+            stun(two, frame, HITSTUN, 16);
+        });
+
+        assert_eq!(advantage(&states, Player::One), Some(5));
+
+        let interaction = jab_interaction(&states);
+        assert_eq!(interaction.frame, 1);
+        assert_eq!(interaction.outcome, EventKind::Hit);
+        assert_eq!(interaction.advantage, 5);
+    }
+
+    #[test]
+    fn blocked_attacks_leave_the_attacker_at_a_disadvantage() {
+        // Player two guards in neutral and goes into blockstun on frame 2,
+        // recovering 3 frames before player one
+        let states = jab(|frame, two| stun(two, frame, BLOCKSTUN, 8));
+
+        let interaction = jab_interaction(&states);
+        assert_eq!(interaction.frame, 2);
+        assert_eq!(interaction.outcome, EventKind::Block);
+        assert_eq!(interaction.move_id, JAB);
+        assert_eq!(interaction.advantage, -3);
+    }
+
+    #[test]
+    fn counter_hits_interrupt_the_defenders_attack() {
+        // Player two is in the startup of a launcher when the jab hits
+        let states = jab(|frame, two| {
+            two.move_id = LAUNCHER;
+            two.move_frame = frame;
+            two.recovery_remaining = 40 - frame;
+            two.attack_flags = 1;
+            two.active_start = 15;
+            two.active_end = 17;
+
+            if frame >= 1 {
+                two.damage_received = 14;
+            }
+            if frame >= 2 {
+                two.attack_flags = 0;
+                two.active_start = 0;
+                two.active_end = 0;
+            }
+            stun(two, frame, HITSTUN, 19);
+        });
+
+        let interaction = jab_interaction(&states);
+        assert_eq!(interaction.frame, 1);
+        assert_eq!(interaction.outcome, EventKind::CounterHit);
+        assert_eq!(interaction.advantage, 8);
+
+        let mut table = FrameDataTable::new();
+        table.add(&interaction);
+        let rows = table.rows();
+        assert_eq!(rows[0].on_counter_hit.map(|a| a.typical), Some(8));
+        assert_eq!(rows[0].on_hit, None);
+    }
+}
@@ -0,0 +1,5 @@
+//! Analysis of captured rounds.
+
+pub mod advantage;
+
+pub use advantage::{FrameAdvantage, FrameDataRow, FrameDataTable, Interaction};
@@ -0,0 +1,460 @@
+//! Delta encoding of frames.
+//!
+//! ```text
+//! frame   mask (varint), round (u8) if bit 0 is set,
+//!         round frame delta (zigzag varint), player one, player two
+//! player  mask (varint) followed by every field whose bit is set:
+//!         0 x (f32)             1 y (f32)               2 z (f32)
+//!         3 input attack (u16)  4 input direction (u16) 5 damage received (u32)
+//!         6 facing (u8)         7 character (u8)        8 last update (zigzag varint delta)
+//!         9 ground state (u8)   10 wakeup (u8)          11 move id (varint)
+//!         12 move frame (varint) 13 recovery remaining (varint)
+//!         14 attack flags (u32) 15 stance flags (u32)   16 rage (u8)
+//!         17 combo count (varint) 18 throwing (u8)      19 throw break window (varint)
+//!         20 active start (varint) 21 active end (varint)
+//! ```
+//!
+//! Ground states are stored as 0 off the ground, 1 face down feet facing,
+//! 2 face down feet away, 3 face up feet facing and 4 face up feet away.
+//! Wakeups are stored as 0 for none and their variant index plus one
+//! otherwise.
+//!
+//! Screen names aren't stored per frame; every frame keeps the ones from the
+//! capture header.
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::{read_character, read_delta, read_varint, write_delta, write_varint, CaptureErrors};
+use crate::globals::{Face, Feet, GroundState, Player, Wakeup};
+use crate::states::game_state::RoundState;
+use crate::states::match_state::MatchHeader;
+use crate::states::player_state::{PlayerInfo, PlayerState};
+
+const ROUND: u128 = 1 << 0;
+
+const X: u128 = 1 << 0;
+const Y: u128 = 1 << 1;
+const Z: u128 = 1 << 2;
+const INPUT_ATTACK: u128 = 1 << 3;
+const INPUT_DIRECTION: u128 = 1 << 4;
+const DAMAGE_RECEIVED: u128 = 1 << 5;
+const FACING: u128 = 1 << 6;
+const CHARACTER: u128 = 1 << 7;
+const LAST_UPDATE: u128 = 1 << 8;
+const GROUND_STATE: u128 = 1 << 9;
+const WAKEUP: u128 = 1 << 10;
+const MOVE_ID: u128 = 1 << 11;
+const MOVE_FRAME: u128 = 1 << 12;
+const RECOVERY_REMAINING: u128 = 1 << 13;
+const ATTACK_FLAGS: u128 = 1 << 14;
+const STANCE_FLAGS: u128 = 1 << 15;
+const RAGE: u128 = 1 << 16;
+const COMBO_COUNT: u128 = 1 << 17;
+const THROWING: u128 = 1 << 18;
+const THROW_BREAK_WINDOW: u128 = 1 << 19;
+const ACTIVE_START: u128 = 1 << 20;
+const ACTIVE_END: u128 = 1 << 21;
+
+/// State the first frame of a round is compared against
+pub fn baseline(header: &MatchHeader, round: u8) -> RoundState {
+    let info = |screen_name: &Option<String>| PlayerInfo {
+        screen_name: screen_name.clone(),
+    };
+
+    RoundState::new(
+        round,
+        0,
+        (info(&header.screen_names.0), info(&header.screen_names.1)),
+        (
+            PlayerState::blank(Player::One, header.characters.0),
+            PlayerState::blank(Player::Two, header.characters.1),
+        ),
+    )
+}
+
+/// Writes the fields of `current` that differ from `previous`
+pub fn encode<W: Write>(w: &mut W, previous: &RoundState, current: &RoundState) -> io::Result<()> {
+    let round_changed = previous.get_round() != current.get_round();
+
+    write_varint(w, if round_changed { ROUND } else { 0 })?;
+    if round_changed {
+        w.write_u8(current.get_round())?;
+    }
+    write_delta(w, previous.get_round_frame(), current.get_round_frame())?;
+
+    for player in [Player::One, Player::Two].iter() {
+        encode_player(
+            w,
+            previous.get_player_state(*player),
+            current.get_player_state(*player),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Reads a frame written by `encode` on top of `previous`
+pub fn decode<R: Read>(r: &mut R, previous: &RoundState) -> Result<RoundState, Box<dyn Error>> {
+    let mask = read_varint(r)?;
+
+    let round = if mask & ROUND != 0 {
+        r.read_u8()?
+    } else {
+        previous.get_round()
+    };
+    let round_frame = read_delta(r, previous.get_round_frame())?;
+
+    let player_state = (
+        decode_player(r, previous.get_player_state(Player::One))?,
+        decode_player(r, previous.get_player_state(Player::Two))?,
+    );
+    let player_info = (
+        previous.get_player_info(Player::One).clone(),
+        previous.get_player_info(Player::Two).clone(),
+    );
+
+    Ok(RoundState::new(round, round_frame, player_info, player_state))
+}
+
+fn encode_player<W: Write>(w: &mut W, previous: &PlayerState, current: &PlayerState) -> io::Result<()> {
+    let mut mask = 0;
+    let mut set = |bit, changed| {
+        if changed {
+            mask |= bit;
+        }
+    };
+
+    // Compare the bits of floats so that NaNs and signed zeros survive
+    set(X, previous.x.to_bits() != current.x.to_bits());
+    set(Y, previous.y.to_bits() != current.y.to_bits());
+    set(Z, previous.z.to_bits() != current.z.to_bits());
+    set(INPUT_ATTACK, previous.input_attack != current.input_attack);
+    set(INPUT_DIRECTION, previous.input_direction != current.input_direction);
+    set(DAMAGE_RECEIVED, previous.damage_received != current.damage_received);
+    set(FACING, previous.facing != current.facing);
+    set(CHARACTER, previous.character != current.character);
+    set(LAST_UPDATE, previous.last_update != current.last_update);
+    set(GROUND_STATE, previous.ground_state != current.ground_state);
+    set(WAKEUP, previous.wakeup != current.wakeup);
+    set(MOVE_ID, previous.move_id != current.move_id);
+    set(MOVE_FRAME, previous.move_frame != current.move_frame);
+    set(RECOVERY_REMAINING, previous.recovery_remaining != current.recovery_remaining);
+    set(ATTACK_FLAGS, previous.attack_flags != current.attack_flags);
+    set(STANCE_FLAGS, previous.stance_flags != current.stance_flags);
+    set(RAGE, previous.rage != current.rage);
+    set(COMBO_COUNT, previous.combo_count != current.combo_count);
+    set(THROWING, previous.throwing != current.throwing);
+    set(THROW_BREAK_WINDOW, previous.throw_break_window != current.throw_break_window);
+    set(ACTIVE_START, previous.active_start != current.active_start);
+    set(ACTIVE_END, previous.active_end != current.active_end);
+
+    write_varint(w, mask)?;
+
+    if mask & X != 0 {
+        w.write_f32::<LittleEndian>(current.x)?;
+    }
+    if mask & Y != 0 {
+        w.write_f32::<LittleEndian>(current.y)?;
+    }
+    if mask & Z != 0 {
+        w.write_f32::<LittleEndian>(current.z)?;
+    }
+    if mask & INPUT_ATTACK != 0 {
+        w.write_u16::<LittleEndian>(current.input_attack)?;
+    }
+    if mask & INPUT_DIRECTION != 0 {
+        w.write_u16::<LittleEndian>(current.input_direction)?;
+    }
+    if mask & DAMAGE_RECEIVED != 0 {
+        w.write_u32::<LittleEndian>(current.damage_received)?;
+    }
+    if mask & FACING != 0 {
+        w.write_u8(current.facing)?;
+    }
+    if mask & CHARACTER != 0 {
+        w.write_u8(current.character as u8)?;
+    }
+    if mask & LAST_UPDATE != 0 {
+        write_delta(w, previous.last_update, current.last_update)?;
+    }
+    if mask & GROUND_STATE != 0 {
+        w.write_u8(ground_state_tag(current.ground_state))?;
+    }
+    if mask & WAKEUP != 0 {
+        w.write_u8(current.wakeup.map_or(0, |wakeup| wakeup as u8 + 1))?;
+    }
+    if mask & MOVE_ID != 0 {
+        write_varint(w, current.move_id.into())?;
+    }
+    if mask & MOVE_FRAME != 0 {
+        write_varint(w, current.move_frame.into())?;
+    }
+    if mask & RECOVERY_REMAINING != 0 {
+        write_varint(w, current.recovery_remaining.into())?;
+    }
+    if mask & ATTACK_FLAGS != 0 {
+        w.write_u32::<LittleEndian>(current.attack_flags)?;
+    }
+    if mask & STANCE_FLAGS != 0 {
+        w.write_u32::<LittleEndian>(current.stance_flags)?;
+    }
+    if mask & RAGE != 0 {
+        w.write_u8(current.rage as u8)?;
+    }
+    if mask & COMBO_COUNT != 0 {
+        write_varint(w, current.combo_count.into())?;
+    }
+    if mask & THROWING != 0 {
+        w.write_u8(current.throwing as u8)?;
+    }
+    if mask & THROW_BREAK_WINDOW != 0 {
+        write_varint(w, current.throw_break_window.into())?;
+    }
+    if mask & ACTIVE_START != 0 {
+        write_varint(w, current.active_start.into())?;
+    }
+    if mask & ACTIVE_END != 0 {
+        write_varint(w, current.active_end.into())?;
+    }
+
+    Ok(())
+}
+
+fn decode_player<R: Read>(r: &mut R, previous: &PlayerState) -> Result<PlayerState, Box<dyn Error>> {
+    let mask = read_varint(r)?;
+    let mut state = previous.clone();
+
+    if mask & X != 0 {
+        state.x = r.read_f32::<LittleEndian>()?;
+    }
+    if mask & Y != 0 {
+        state.y = r.read_f32::<LittleEndian>()?;
+    }
+    if mask & Z != 0 {
+        state.z = r.read_f32::<LittleEndian>()?;
+    }
+    if mask & INPUT_ATTACK != 0 {
+        state.input_attack = r.read_u16::<LittleEndian>()?;
+    }
+    if mask & INPUT_DIRECTION != 0 {
+        state.input_direction = r.read_u16::<LittleEndian>()?;
+    }
+    if mask & DAMAGE_RECEIVED != 0 {
+        state.damage_received = r.read_u32::<LittleEndian>()?;
+    }
+    if mask & FACING != 0 {
+        state.facing = r.read_u8()?;
+    }
+    if mask & CHARACTER != 0 {
+        state.character = read_character(r)?;
+    }
+    if mask & LAST_UPDATE != 0 {
+        state.last_update = read_delta(r, previous.last_update)?;
+    }
+    if mask & GROUND_STATE != 0 {
+        state.ground_state = read_ground_state(r)?;
+    }
+    if mask & WAKEUP != 0 {
+        state.wakeup = read_wakeup(r)?;
+    }
+    if mask & MOVE_ID != 0 {
+        state.move_id = read_u32_varint(r)?;
+    }
+    if mask & MOVE_FRAME != 0 {
+        state.move_frame = read_u32_varint(r)?;
+    }
+    if mask & RECOVERY_REMAINING != 0 {
+        state.recovery_remaining = read_u32_varint(r)?;
+    }
+    if mask & ATTACK_FLAGS != 0 {
+        state.attack_flags = r.read_u32::<LittleEndian>()?;
+    }
+    if mask & STANCE_FLAGS != 0 {
+        state.stance_flags = r.read_u32::<LittleEndian>()?;
+    }
+    if mask & RAGE != 0 {
+        state.rage = r.read_u8()? != 0;
+    }
+    if mask & COMBO_COUNT != 0 {
+        state.combo_count = read_u32_varint(r)?;
+    }
+    if mask & THROWING != 0 {
+        state.throwing = r.read_u8()? != 0;
+    }
+    if mask & THROW_BREAK_WINDOW != 0 {
+        state.throw_break_window = read_u32_varint(r)?;
+    }
+    if mask & ACTIVE_START != 0 {
+        state.active_start = read_u32_varint(r)?;
+    }
+    if mask & ACTIVE_END != 0 {
+        state.active_end = read_u32_varint(r)?;
+    }
+
+    Ok(state)
+}
+
+fn read_u32_varint<R: Read>(r: &mut R) -> Result<u32, Box<dyn Error>> {
+    let value = read_varint(r)?;
+    if value > u32::MAX as u128 {
+        return Err(CaptureErrors::UnknownValue("u32", value).into());
+    }
+
+    Ok(value as u32)
+}
+
+fn ground_state_tag(ground_state: Option<GroundState>) -> u8 {
+    match ground_state {
+        None => 0,
+        Some((Face::Down, Feet::Facing)) => 1,
+        Some((Face::Down, Feet::Away)) => 2,
+        Some((Face::Up, Feet::Facing)) => 3,
+        Some((Face::Up, Feet::Away)) => 4,
+    }
+}
+
+fn read_ground_state<R: Read>(r: &mut R) -> Result<Option<GroundState>, Box<dyn Error>> {
+    match r.read_u8()? {
+        0 => Ok(None),
+        1 => Ok(Some((Face::Down, Feet::Facing))),
+        2 => Ok(Some((Face::Down, Feet::Away))),
+        3 => Ok(Some((Face::Up, Feet::Facing))),
+        4 => Ok(Some((Face::Up, Feet::Away))),
+        value => Err(CaptureErrors::UnknownValue("GroundState", value as u128).into()),
+    }
+}
+
+fn read_wakeup<R: Read>(r: &mut R) -> Result<Option<Wakeup>, Box<dyn Error>> {
+    match r.read_u8()? {
+        0 => Ok(None),
+        value => num::FromPrimitive::from_u8(value - 1)
+            .map(Some)
+            .ok_or_else(|| CaptureErrors::UnknownValue("Wakeup", value as u128).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::globals::Character;
+
+    fn players() -> (PlayerState, PlayerState) {
+        (
+            PlayerState::blank(Player::One, Character::Law),
+            PlayerState::blank(Player::Two, Character::Paul),
+        )
+    }
+
+    fn state(round: u8, round_frame: u128, player_state: (PlayerState, PlayerState)) -> RoundState {
+        let info = (PlayerInfo { screen_name: None }, PlayerInfo { screen_name: None });
+        RoundState::new(round, round_frame, info, player_state)
+    }
+
+    fn round_trip(previous: &RoundState, current: &RoundState) -> (Vec<u8>, RoundState) {
+        let mut bytes = Vec::new();
+        encode(&mut bytes, previous, current).unwrap();
+        let decoded = decode(&mut Cursor::new(&bytes), previous).unwrap();
+
+        (bytes, decoded)
+    }
+
+    /// Frames hold no NaNs here, so equal JSON means every field matches
+    fn assert_same(left: &RoundState, right: &RoundState) {
+        assert_eq!(serde_json::to_string(left).unwrap(), serde_json::to_string(right).unwrap());
+    }
+
+    #[test]
+    fn unchanged_frames_only_write_masks() {
+        let previous = state(1, 40, players());
+        let (bytes, decoded) = round_trip(&previous, &previous);
+
+        assert_eq!(bytes, vec![0, 0, 0, 0]);
+        assert_same(&decoded, &previous);
+    }
+
+    #[test]
+    fn masks_only_cover_changed_fields() {
+        let previous = state(1, 40, players());
+        let (mut one, two) = players();
+        one.damage_received = 12;
+        let current = state(1, 41, (one, two));
+
+        let (bytes, decoded) = round_trip(&previous, &current);
+
+        assert_eq!(bytes, vec![0, 2, DAMAGE_RECEIVED as u8, 12, 0, 0, 0, 0]);
+        assert_same(&decoded, &current);
+    }
+
+    #[test]
+    fn every_field_round_trips() {
+        let (mut one, mut two) = players();
+        two.character = Character::King;
+        two.x = -1.25;
+        two.y = f32::MIN_POSITIVE;
+        two.z = -0.0;
+        two.input_attack = 4;
+        two.input_direction = 16;
+        two.damage_received = 180;
+        two.facing = 1;
+        two.ground_state = Some((Face::Down, Feet::Away));
+        two.wakeup = Some(Wakeup::RollForwardLowAttack);
+        two.move_id = 1_000_000;
+        two.move_frame = 12;
+        two.recovery_remaining = 30;
+        two.attack_flags = 0x8000_0001;
+        two.active_start = 10;
+        two.active_end = 12;
+        two.stance_flags = u32::MAX;
+        two.rage = true;
+        two.combo_count = 7;
+        two.throwing = true;
+        two.throw_break_window = 20;
+
+        // Both the round frame and the update time go backwards
+        let (mut before, _) = players();
+        before.last_update = 1_700_000_000_000;
+        one.last_update = 0;
+
+        let previous = state(1, 3000, (before, players().1));
+        let current = state(2, 0, (one, two));
+        let (_, decoded) = round_trip(&previous, &current);
+
+        assert_eq!(decoded.get_round(), 2);
+        assert_eq!(decoded.get_round_frame(), 0);
+        assert_same(&decoded, &current);
+        assert_eq!(decoded.get_player_state(Player::Two).z().to_bits(), (-0.0f32).to_bits());
+    }
+
+    #[test]
+    fn consecutive_frames_decode_on_top_of_each_other() {
+        let frames: Vec<RoundState> = (0..10)
+            .map(|frame| {
+                let (mut one, mut two) = players();
+                one.x = frame as f32 * 0.5;
+                two.input_direction = if frame % 3 == 0 { 32 } else { 16 };
+                state(1, frame * 2, (one, two))
+            })
+            .collect();
+
+        let mut bytes = Vec::new();
+        let mut previous = state(1, 0, players());
+        for frame in &frames {
+            encode(&mut bytes, &previous, frame).unwrap();
+            previous = frame.clone();
+        }
+
+        let mut reader = Cursor::new(&bytes);
+        let mut previous = state(1, 0, players());
+        for frame in &frames {
+            let decoded = decode(&mut reader, &previous).unwrap();
+            assert_same(&decoded, frame);
+            previous = decoded;
+        }
+        assert_eq!(reader.position() as usize, bytes.len());
+    }
+}
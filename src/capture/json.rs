@@ -0,0 +1,55 @@
+//! Conversion between captures and JSON match documents.
+//!
+//! Besides match documents, JSON files holding the plain array of frames
+//! written for a single round by older builds can be converted as well.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::{CaptureWriter, EXTENSION};
+use crate::states::game_state::RoundState;
+use crate::states::match_state::MatchState;
+
+/// Loads a JSON match document or a single round written by older builds
+pub fn load<P: AsRef<Path>>(path: P) -> Result<MatchState, Box<dyn Error>> {
+    let contents = std::fs::read(path)?;
+
+    // Untagged enums can't buffer the u128 fields of frames, so tell the two
+    // layouts apart by whether the document is an object or an array
+    match contents.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') => {
+            let states: Vec<RoundState> = serde_json::from_slice(&contents)?;
+            let first = states.first().ok_or("The round has no frames")?;
+            let mut match_state = MatchState::new(first, None);
+
+            // Old files don't tell whether the round was over when saved
+            match_state.push_round(states, false);
+            Ok(match_state)
+        }
+        _ => Ok(serde_json::from_slice(&contents)?),
+    }
+}
+
+pub fn save<P: AsRef<Path>>(path: P, match_state: &MatchState) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, match_state)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Converts `input` into `output`. A capture is written when `output`
+/// has the capture extension and JSON otherwise; the input format is
+/// detected from its contents.
+pub fn convert<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> Result<(), Box<dyn Error>> {
+    let match_state = super::load_match(input)?;
+
+    if output.as_ref().extension().map_or(false, |e| e == EXTENSION) {
+        CaptureWriter::write_match(BufWriter::new(File::create(output)?), &match_state)?;
+        Ok(())
+    } else {
+        save(output, &match_state)
+    }
+}
@@ -0,0 +1,265 @@
+//! Compact binary container for captured matches.
+//!
+//! A capture starts with a header followed by a stream of tagged records:
+//!
+//! ```text
+//! header   magic "TKCP", schema version (u16), game version,
+//!          characters (u8 each), screen names, start time (u64)
+//! records  ROUND_BEGIN round (u8)
+//!          FRAME       frame delta against the previous frame of the round
+//!          ROUND_END   round result
+//!          MATCH_END   no payload, marks the capture as complete
+//! ```
+//!
+//! Integers are little endian. Variable length values are LEB128 varints,
+//! strings are a varint length followed by UTF-8 bytes, and optional
+//! strings are prefixed with a presence byte.
+//!
+//! Frames only store the fields that changed since the previous frame of
+//! the round, see [`frame`] for the layout. The first frame of each round
+//! is compared against blank player states.
+
+pub mod frame;
+pub mod json;
+pub mod reader;
+pub mod stream;
+pub mod writer;
+
+pub use reader::{CaptureReader, Record};
+pub use stream::StreamWriter;
+pub use writer::CaptureWriter;
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::globals::{Character, Player};
+use crate::states::match_state::{MatchHeader, MatchState};
+
+/// Bytes every capture file starts with
+pub const MAGIC: &[u8; 4] = b"TKCP";
+/// Layout version written by this build. Bump whenever the layout of the
+/// header or of any record changes.
+pub const SCHEMA_VERSION: u16 = 5;
+/// Extension of capture files
+pub const EXTENSION: &str = "tkcp";
+
+pub(crate) const ROUND_BEGIN: u8 = 1;
+pub(crate) const FRAME: u8 = 2;
+pub(crate) const ROUND_END: u8 = 3;
+pub(crate) const MATCH_END: u8 = 4;
+
+#[derive(Debug)]
+pub enum CaptureErrors {
+    /// The file doesn't start with the capture magic bytes
+    NotACapture,
+    /// Schema version of a capture written by a newer build
+    UnsupportedSchema(u16),
+    /// Tag of a record this build doesn't know
+    UnknownRecord(u8),
+    /// Name of the type being decoded and the value that didn't match it
+    UnknownValue(&'static str, u128),
+    /// A frame was read before any round began
+    FrameOutsideRound,
+    /// The capture ends before its `MATCH_END` record
+    Truncated,
+    /// A partial capture ends right after its header, without a single frame
+    NoFrames,
+}
+
+impl std::fmt::Display for CaptureErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureErrors::NotACapture => write!(f, "Not a capture file"),
+            CaptureErrors::UnsupportedSchema(version) => write!(
+                f,
+                "Capture schema version {} is newer than the supported version {}",
+                version, SCHEMA_VERSION
+            ),
+            CaptureErrors::UnknownRecord(tag) => write!(f, "Unknown record tag {}", tag),
+            CaptureErrors::UnknownValue(kind, value) => {
+                write!(f, "Unknown {} value {}", kind, value)
+            }
+            CaptureErrors::FrameOutsideRound => write!(f, "Frame recorded outside of a round"),
+            CaptureErrors::Truncated => write!(f, "Capture ends before the end of the match"),
+            CaptureErrors::NoFrames => write!(f, "No frames after the capture header"),
+        }
+    }
+}
+
+impl Error for CaptureErrors {}
+
+/// Loads a match from either a capture or a JSON file, telling them apart
+/// by their contents
+pub fn load_match<P: AsRef<Path>>(path: P) -> Result<MatchState, Box<dyn Error>> {
+    match CaptureReader::open(&path) {
+        Ok(reader) => reader.read_match(),
+        Err(e) => match e.downcast_ref::<CaptureErrors>() {
+            Some(CaptureErrors::NotACapture) => json::load(&path),
+            _ => Err(e),
+        },
+    }
+}
+
+/// Writes the capture header for `header`
+pub(crate) fn write_header<W: Write>(w: &mut W, header: &MatchHeader) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_u16::<LittleEndian>(SCHEMA_VERSION)?;
+    write_optional_string(w, header.game_version.as_deref())?;
+    w.write_u8(header.characters.0 as u8)?;
+    w.write_u8(header.characters.1 as u8)?;
+    write_optional_string(w, header.screen_names.0.as_deref())?;
+    write_optional_string(w, header.screen_names.1.as_deref())?;
+    w.write_u64::<LittleEndian>(header.started_at)
+}
+
+/// Reads the capture header, returning it along with the schema version
+pub(crate) fn read_header<R: Read>(r: &mut R) -> Result<(MatchHeader, u16), Box<dyn Error>> {
+    let mut magic = [0u8; 4];
+    r.read_exact(&mut magic).map_err(|_| CaptureErrors::NotACapture)?;
+    if &magic != MAGIC {
+        return Err(CaptureErrors::NotACapture.into());
+    }
+
+    let schema = r.read_u16::<LittleEndian>()?;
+    if schema > SCHEMA_VERSION {
+        return Err(CaptureErrors::UnsupportedSchema(schema).into());
+    }
+
+    let header = MatchHeader {
+        game_version: read_optional_string(r)?,
+        characters: (read_character(r)?, read_character(r)?),
+        screen_names: (read_optional_string(r)?, read_optional_string(r)?),
+        started_at: r.read_u64::<LittleEndian>()?,
+        rounds_won: (0, 0),
+        winner: None,
+    };
+
+    Ok((header, schema))
+}
+
+pub(crate) fn write_varint<W: Write>(w: &mut W, mut value: u128) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            return w.write_u8(byte);
+        }
+        w.write_u8(byte | 0x80)?;
+    }
+}
+
+pub(crate) fn read_varint<R: Read>(r: &mut R) -> io::Result<u128> {
+    let mut value = 0u128;
+
+    for shift in (0..128).step_by(7) {
+        let byte = r.read_u8()?;
+        value |= ((byte & 0x7F) as u128) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "varint is too long"))
+}
+
+/// Writes the signed difference `current - previous` as a zigzag varint
+pub(crate) fn write_delta<W: Write>(w: &mut W, previous: u128, current: u128) -> io::Result<()> {
+    let delta = current.wrapping_sub(previous) as i128;
+    write_varint(w, ((delta << 1) ^ (delta >> 127)) as u128)
+}
+
+/// Reads a delta written by `write_delta` and applies it to `previous`
+pub(crate) fn read_delta<R: Read>(r: &mut R, previous: u128) -> io::Result<u128> {
+    let zigzag = read_varint(r)?;
+    let delta = ((zigzag >> 1) as i128) ^ -((zigzag & 1) as i128);
+
+    Ok(previous.wrapping_add(delta as u128))
+}
+
+fn write_optional_string<W: Write>(w: &mut W, value: Option<&str>) -> io::Result<()> {
+    match value {
+        Some(value) => {
+            w.write_u8(1)?;
+            write_varint(w, value.len() as u128)?;
+            w.write_all(value.as_bytes())
+        }
+        None => w.write_u8(0),
+    }
+}
+
+fn read_optional_string<R: Read>(r: &mut R) -> Result<Option<String>, Box<dyn Error>> {
+    if r.read_u8()? == 0 {
+        return Ok(None);
+    }
+
+    let mut bytes = vec![0u8; read_varint(r)? as usize];
+    r.read_exact(&mut bytes)?;
+    Ok(Some(String::from_utf8(bytes)?))
+}
+
+pub(crate) fn read_character<R: Read>(r: &mut R) -> Result<Character, Box<dyn Error>> {
+    let value = r.read_u8()?;
+    num::FromPrimitive::from_u8(value)
+        .ok_or_else(|| CaptureErrors::UnknownValue("Character", value as u128).into())
+}
+
+pub(crate) fn player_tag(player: Option<Player>) -> u8 {
+    match player {
+        None => 0,
+        Some(Player::One) => 1,
+        Some(Player::Two) => 2,
+    }
+}
+
+pub(crate) fn read_player_tag<R: Read>(r: &mut R) -> Result<Option<Player>, Box<dyn Error>> {
+    match r.read_u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(Player::One)),
+        2 => Ok(Some(Player::Two)),
+        value => Err(CaptureErrors::UnknownValue("Player", value as u128).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn varints_round_trip() {
+        for value in &[0, 1, 0x7F, 0x80, 300, u32::MAX as u128, u64::MAX as u128, u128::MAX] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, *value).unwrap();
+
+            assert_eq!(read_varint(&mut Cursor::new(&bytes)).unwrap(), *value);
+        }
+    }
+
+    #[test]
+    fn deltas_round_trip_in_both_directions() {
+        let pairs = [(0, 0), (10, 11), (11, 10), (6000, 0), (0, 6000), (5, u128::MAX), (u128::MAX, 5)];
+
+        for (previous, current) in pairs.iter() {
+            let mut bytes = Vec::new();
+            write_delta(&mut bytes, *previous, *current).unwrap();
+
+            assert_eq!(read_delta(&mut Cursor::new(&bytes), *previous).unwrap(), *current);
+        }
+    }
+
+    #[test]
+    fn small_deltas_take_a_single_byte() {
+        let mut bytes = Vec::new();
+        write_delta(&mut bytes, 10, 11).unwrap();
+        write_delta(&mut bytes, 11, 10).unwrap();
+
+        // Zigzag keeps small negative deltas as short as positive ones
+        assert_eq!(bytes, vec![2, 1]);
+    }
+}
@@ -0,0 +1,156 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use super::{frame, read_header, read_player_tag, read_varint, CaptureErrors};
+use super::{FRAME, MATCH_END, ROUND_BEGIN, ROUND_END};
+use crate::globals::Side;
+use crate::states::game_state::RoundState;
+use crate::states::match_state::{MatchHeader, MatchState, RoundFinish, RoundRecord, RoundResult};
+
+/// Single record of a capture
+pub enum Record {
+    RoundBegin(u8),
+    Frame(RoundState),
+    RoundEnd(RoundResult),
+    MatchEnd,
+}
+
+/// Reads a capture one record at a time
+pub struct CaptureReader<R: Read> {
+    inner: R,
+    header: MatchHeader,
+    schema: u16,
+    /// Last frame read in the current round
+    previous: Option<RoundState>,
+    finished: bool,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Reads the capture header
+    pub fn new(mut inner: R) -> Result<Self, Box<dyn Error>> {
+        let (header, schema) = read_header(&mut inner)?;
+
+        Ok(Self {
+            inner,
+            header,
+            schema,
+            previous: None,
+            finished: false,
+        })
+    }
+
+    /// Header of the match, without any round results
+    pub fn header(&self) -> &MatchHeader {
+        &self.header
+    }
+
+    /// Schema version the capture was written with
+    pub fn schema(&self) -> u16 {
+        self.schema
+    }
+
+    /// Reads the next record, returning `None` after the end of the match.
+    /// Fails with `Truncated` if the capture ends before that.
+    pub fn next_record(&mut self) -> Result<Option<Record>, Box<dyn Error>> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        self.read_record().map(Some).map_err(|e| {
+            match e.downcast_ref::<io::Error>() {
+                Some(io_error) if io_error.kind() == io::ErrorKind::UnexpectedEof => {
+                    CaptureErrors::Truncated.into()
+                }
+                _ => e,
+            }
+        })
+    }
+
+    /// Reads every remaining record into a match
+    pub fn read_match(mut self) -> Result<MatchState, Box<dyn Error>> {
+        let mut match_state = MatchState::with_header(self.header.clone());
+        let mut states = Vec::new();
+
+        while let Some(record) = self.next_record()? {
+            match record {
+                Record::RoundBegin(_) => states.clear(),
+                Record::Frame(state) => states.push(state),
+                Record::RoundEnd(result) => match_state.push_record(RoundRecord {
+                    result,
+                    states: std::mem::replace(&mut states, Vec::new()),
+                }),
+                Record::MatchEnd => (),
+            };
+        }
+
+        Ok(match_state)
+    }
+
+    fn read_record(&mut self) -> Result<Record, Box<dyn Error>> {
+        let record = match self.inner.read_u8()? {
+            ROUND_BEGIN => {
+                let round = self.inner.read_u8()?;
+                self.previous = Some(frame::baseline(&self.header, round));
+                Record::RoundBegin(round)
+            }
+            FRAME => {
+                let previous = self.previous.as_ref().ok_or(CaptureErrors::FrameOutsideRound)?;
+                let state = frame::decode(&mut self.inner, previous)?;
+                self.previous = Some(state.clone());
+                Record::Frame(state)
+            }
+            ROUND_END => {
+                self.previous = None;
+                Record::RoundEnd(self.read_result()?)
+            }
+            MATCH_END => {
+                self.finished = true;
+                Record::MatchEnd
+            }
+            tag => return Err(CaptureErrors::UnknownRecord(tag).into()),
+        };
+
+        Ok(record)
+    }
+
+    fn read_result(&mut self) -> Result<RoundResult, Box<dyn Error>> {
+        let r = &mut self.inner;
+
+        let round = r.read_u8()?;
+        let winner = read_player_tag(r)?;
+        let finish = match r.read_u8()? {
+            0 => RoundFinish::KnockOut,
+            1 => RoundFinish::TimeOut,
+            2 => RoundFinish::Unfinished,
+            value => return Err(CaptureErrors::UnknownValue("RoundFinish", value as u128).into()),
+        };
+
+        let mut side = || -> Result<Side, Box<dyn Error>> {
+            match r.read_u8()? {
+                0 => Ok(Side::Left),
+                1 => Ok(Side::Right),
+                value => Err(CaptureErrors::UnknownValue("Side", value as u128).into()),
+            }
+        };
+        let starting_sides = (side()?, side()?);
+
+        Ok(RoundResult {
+            round,
+            winner,
+            finish,
+            starting_sides,
+            final_health: (r.read_u32::<LittleEndian>()?, r.read_u32::<LittleEndian>()?),
+            frames: read_varint(r)? as usize,
+        })
+    }
+}
@@ -0,0 +1,339 @@
+//! Crash-safe streaming of captures.
+//!
+//! Frames are appended to a `.partial` file in the output directory as they
+//! are captured, flushed and synced to disk in batches and at every round
+//! end. Once the match is over the file is completed and renamed into place
+//! in a single step, so a finished capture is never seen half written.
+//! Partial files left behind by a crash are salvaged with [`salvage`].
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use super::{CaptureErrors, CaptureReader, CaptureWriter, Record, EXTENSION};
+use crate::states::game_state::RoundState;
+use crate::states::match_state::{MatchHeader, MatchState, RoundRecord, RoundResult};
+
+/// Extension of captures still being written
+pub const PARTIAL_EXTENSION: &str = "partial";
+/// Frames written in between syncs, about a second of gameplay
+pub const BATCH_FRAMES: usize = 60;
+
+/// Appends a match to a partial capture file as it's being played
+pub struct StreamWriter {
+    writer: CaptureWriter<BufWriter<File>>,
+    path: PathBuf,
+    /// Frames written since the last sync
+    pending: usize,
+}
+
+impl StreamWriter {
+    /// Creates a partial capture for the match described by `header` in `directory`
+    pub fn create<P: AsRef<Path>>(directory: P, header: &MatchHeader) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+
+        let path = directory
+            .as_ref()
+            .join(format!("{}.{}.{}", uuid::Uuid::new_v4(), EXTENSION, PARTIAL_EXTENSION));
+        let writer = CaptureWriter::new(BufWriter::new(File::create(&path)?), header)?;
+
+        // Get the header on disk right away so that the file can always be salvaged
+        let mut stream = Self {
+            writer,
+            path,
+            pending: 0,
+        };
+        stream.sync()?;
+        Ok(stream)
+    }
+
+    /// Path of the partial capture
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a frame, syncing once a whole batch has been written
+    pub fn write_frame(&mut self, state: &RoundState) -> io::Result<()> {
+        self.writer.write_frame(state)?;
+        self.pending += 1;
+
+        if self.pending >= BATCH_FRAMES {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Records the result of the current round and syncs it to disk
+    pub fn end_round(&mut self, result: &RoundResult) -> io::Result<()> {
+        self.writer.end_round(result)?;
+        self.sync()
+    }
+
+    /// Flushes buffered frames and waits for them to reach the disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().get_ref().sync_data()?;
+        self.pending = 0;
+
+        Ok(())
+    }
+
+    /// Completes the capture and moves it to `destination`
+    pub fn finalize<P: AsRef<Path>>(self, destination: P) -> io::Result<()> {
+        let file = self.writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&self.path, &destination)?;
+        sync_parent(destination.as_ref())
+    }
+
+    /// Removes the partial capture, for when the match was saved some other way
+    pub fn discard(self) -> io::Result<()> {
+        drop(self.writer);
+        fs::remove_file(&self.path)
+    }
+}
+
+/// Writes a file through `write` under a temporary name, then renames it to
+/// `path` once it's completely on disk
+pub fn write_atomically<P, F>(path: P, write: F) -> Result<(), Box<dyn Error>>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), Box<dyn Error>>,
+{
+    let path = path.as_ref();
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+
+    let mut writer = BufWriter::new(File::create(&temp)?);
+    write(&mut writer)?;
+
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp, path)?;
+    Ok(sync_parent(path)?)
+}
+
+/// Partial captures left in `directory` by an interrupted capture
+pub fn partial_files<P: AsRef<Path>>(directory: P) -> io::Result<Vec<PathBuf>> {
+    if !directory.as_ref().is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == PARTIAL_EXTENSION) {
+            paths.push(path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Reads whatever a partial capture holds. Rounds that were completed keep
+/// their result, and the frames of the round that was interrupted are kept
+/// as an unfinished round. Fails on an unreadable header, and with
+/// `NoFrames` when the header is all the file holds.
+pub fn salvage<P: AsRef<Path>>(path: P) -> Result<MatchState, Box<dyn Error>> {
+    let mut reader = CaptureReader::open(path)?;
+    let mut match_state = MatchState::with_header(reader.header().clone());
+    let mut states = Vec::new();
+
+    // Anything after the first record that can't be read was never synced
+    while let Ok(Some(record)) = reader.next_record() {
+        match record {
+            Record::RoundBegin(_) => states.clear(),
+            Record::Frame(state) => states.push(state),
+            Record::RoundEnd(result) => match_state.push_record(RoundRecord {
+                result,
+                states: std::mem::take(&mut states),
+            }),
+            Record::MatchEnd => (),
+        };
+    }
+
+    match_state.push_round(states, false);
+    if match_state.rounds().is_empty() {
+        return Err(CaptureErrors::NoFrames.into());
+    }
+    Ok(match_state)
+}
+
+/// Makes a rename in the parent directory of `path` durable
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+/// Directories can't be opened as files on Windows, so persisting the rename
+/// is left to the file system
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::{Character, Player};
+    use crate::states::match_state::RoundFinish;
+    use crate::states::player_state::{PlayerInfo, PlayerState};
+
+    /// Fresh directory under the system temp directory, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!("stream-test-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn state(round: u8, frame: u128, damage: u32) -> RoundState {
+        let info = || PlayerInfo { screen_name: None };
+        let mut one = PlayerState::blank(Player::One, Character::Law);
+        one.damage_received = damage;
+
+        RoundState::new(round, frame, (info(), info()), (one, PlayerState::blank(Player::Two, Character::Paul)))
+    }
+
+    /// Round one fully played and ended, and two frames of round two
+    fn rounds() -> (Vec<RoundState>, Vec<RoundState>) {
+        (
+            vec![state(1, 0, 0), state(1, 1, 30), state(1, 2, 60)],
+            vec![state(2, 0, 0), state(2, 1, 15)],
+        )
+    }
+
+    fn header() -> MatchHeader {
+        MatchState::new(&rounds().0[0], None).header().clone()
+    }
+
+    fn file_len(path: &Path) -> usize {
+        fs::metadata(path).unwrap().len() as usize
+    }
+
+    /// Streams `rounds()`, returning the writer along with the file length
+    /// after the header, after round one ended and after everything
+    fn stream(directory: &Path) -> (StreamWriter, [usize; 3]) {
+        let (first, second) = rounds();
+        let mut stream = StreamWriter::create(directory, &header()).unwrap();
+        let header = file_len(stream.path());
+
+        for state in &first {
+            stream.write_frame(state).unwrap();
+        }
+        stream.end_round(&RoundResult::from_states(&first, true).unwrap()).unwrap();
+        let first_round = file_len(stream.path());
+
+        for state in &second {
+            stream.write_frame(state).unwrap();
+        }
+        stream.sync().unwrap();
+
+        let len = file_len(stream.path());
+        (stream, [header, first_round, len])
+    }
+
+    /// Salvages a copy of the partial capture cut down to `len` bytes
+    fn salvage_truncated(stream: &StreamWriter, len: usize) -> Result<MatchState, Box<dyn Error>> {
+        let bytes = fs::read(stream.path()).unwrap();
+        let copy = stream.path().with_extension(format!("{}.{}", len, PARTIAL_EXTENSION));
+        fs::write(&copy, &bytes[..len]).unwrap();
+
+        let salvaged = salvage(&copy);
+        fs::remove_file(&copy).unwrap();
+        salvaged
+    }
+
+    fn round_sizes(match_state: &MatchState) -> Vec<(u8, RoundFinish, usize)> {
+        match_state
+            .rounds()
+            .iter()
+            .map(|r| (r.result.round, r.result.finish, r.states.len()))
+            .collect()
+    }
+
+    #[test]
+    fn salvages_completed_and_interrupted_rounds() {
+        let directory = TempDir::new();
+        let (stream, [header, first_round, len]) = stream(&directory.0);
+
+        assert!(salvage_truncated(&stream, header - 1).is_err());
+        match salvage_truncated(&stream, header) {
+            Err(e) => assert!(matches!(e.downcast_ref::<CaptureErrors>(), Some(CaptureErrors::NoFrames))),
+            Ok(_) => panic!("salvaged a capture without frames"),
+        }
+
+        let ended = round_sizes(&salvage_truncated(&stream, first_round).unwrap());
+        assert_eq!(ended, vec![(1, RoundFinish::TimeOut, 3)]);
+
+        // The last frame of round two was only partly written
+        let interrupted = round_sizes(&salvage_truncated(&stream, len - 1).unwrap());
+        assert_eq!(interrupted, vec![(1, RoundFinish::TimeOut, 3), (2, RoundFinish::Unfinished, 1)]);
+
+        let synced = round_sizes(&salvage_truncated(&stream, len).unwrap());
+        assert_eq!(synced, vec![(1, RoundFinish::TimeOut, 3), (2, RoundFinish::Unfinished, 2)]);
+
+        // Cutting the file later never salvages fewer frames, and only cuts
+        // inside the first frame leave nothing to salvage
+        let mut salvaged = 0;
+        for cut in header + 1..=len {
+            let frames = match salvage_truncated(&stream, cut) {
+                Ok(match_state) => match_state.rounds().iter().map(|r| r.states.len()).sum(),
+                Err(_) if salvaged == 0 => 0,
+                Err(e) => panic!("salvaging {} bytes failed after frames were salvaged: {}", cut, e),
+            };
+            assert!(frames >= salvaged, "{} frames salvaged at {} bytes after {}", frames, cut, salvaged);
+            salvaged = frames;
+        }
+        assert_eq!(salvaged, 5);
+    }
+
+    #[test]
+    fn finalized_captures_replace_the_partial_file() {
+        let directory = TempDir::new();
+        let (mut stream, _) = stream(&directory.0);
+        stream.end_round(&RoundResult::from_states(&rounds().1, false).unwrap()).unwrap();
+
+        let partial = stream.path().to_path_buf();
+        assert_eq!(partial_files(&directory.0).unwrap(), vec![partial.clone()]);
+
+        let destination = directory.0.join(format!("match.{}", EXTENSION));
+        stream.finalize(&destination).unwrap();
+
+        assert!(!partial.exists());
+        assert!(partial_files(&directory.0).unwrap().is_empty());
+        let finished = CaptureReader::open(&destination).unwrap().read_match().unwrap();
+        assert_eq!(round_sizes(&finished), vec![(1, RoundFinish::TimeOut, 3), (2, RoundFinish::Unfinished, 2)]);
+    }
+
+    #[test]
+    fn discarded_captures_are_removed() {
+        let directory = TempDir::new();
+        let (stream, _) = stream(&directory.0);
+        fs::write(directory.0.join("other.tkcp"), b"").unwrap();
+
+        let partial = stream.path().to_path_buf();
+        stream.discard().unwrap();
+
+        assert!(!partial.exists());
+        assert!(partial_files(&directory.0).unwrap().is_empty());
+        assert!(partial_files(directory.0.join("missing")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+use std::error::Error;
+use std::io::{self, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use super::{frame, player_tag, write_header, write_varint, MATCH_END, FRAME, ROUND_BEGIN, ROUND_END};
+use crate::globals::Side;
+use crate::states::game_state::RoundState;
+use crate::states::match_state::{MatchHeader, MatchState, RoundFinish, RoundResult};
+
+/// Writes a match as a capture, one record at a time
+pub struct CaptureWriter<W: Write> {
+    inner: W,
+    header: MatchHeader,
+    /// Last frame written in the current round
+    previous: Option<RoundState>,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the capture header and gets ready for the first round
+    pub fn new(mut inner: W, header: &MatchHeader) -> io::Result<Self> {
+        write_header(&mut inner, header)?;
+
+        Ok(Self {
+            inner,
+            header: header.clone(),
+            previous: None,
+        })
+    }
+
+    /// Writes every round of `match_state` and finishes the capture
+    pub fn write_match(inner: W, match_state: &MatchState) -> Result<W, Box<dyn Error>> {
+        let mut writer = Self::new(inner, match_state.header())?;
+
+        for record in match_state.rounds() {
+            writer.begin_round(record.result.round)?;
+            for state in &record.states {
+                writer.write_frame(state)?;
+            }
+            writer.end_round(&record.result)?;
+        }
+
+        Ok(writer.finish()?)
+    }
+
+    pub fn begin_round(&mut self, round: u8) -> io::Result<()> {
+        self.inner.write_u8(ROUND_BEGIN)?;
+        self.inner.write_u8(round)?;
+        self.previous = Some(frame::baseline(&self.header, round));
+
+        Ok(())
+    }
+
+    /// Writes a frame of the current round, beginning one if needed
+    pub fn write_frame(&mut self, state: &RoundState) -> io::Result<()> {
+        if self.previous.is_none() {
+            self.begin_round(state.get_round())?;
+        }
+
+        self.inner.write_u8(FRAME)?;
+        frame::encode(&mut self.inner, self.previous.as_ref().unwrap(), state)?;
+        self.previous = Some(state.clone());
+
+        Ok(())
+    }
+
+    pub fn end_round(&mut self, result: &RoundResult) -> io::Result<()> {
+        let side = |side: Side| match side {
+            Side::Left => 0,
+            Side::Right => 1,
+        };
+        let finish = match result.finish {
+            RoundFinish::KnockOut => 0,
+            RoundFinish::TimeOut => 1,
+            RoundFinish::Unfinished => 2,
+        };
+
+        let w = &mut self.inner;
+        w.write_u8(ROUND_END)?;
+        w.write_u8(result.round)?;
+        w.write_u8(player_tag(result.winner))?;
+        w.write_u8(finish)?;
+        w.write_u8(side(result.starting_sides.0))?;
+        w.write_u8(side(result.starting_sides.1))?;
+        w.write_u32::<LittleEndian>(result.final_health.0)?;
+        w.write_u32::<LittleEndian>(result.final_health.1)?;
+        write_varint(w, result.frames as u128)?;
+
+        self.previous = None;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Marks the capture as complete and returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_u8(MATCH_END)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
@@ -1 +1,40 @@
+//! Errors reported when the program exits.
 
+use std::error::Error;
+use std::fmt;
+
+/// Error `main` returns, printed along with what was being done when it happened
+pub struct MainError {
+    context: Option<String>,
+    source: Box<dyn Error>,
+}
+
+/// Adds what was being done to errors on their way out of `main`
+pub trait Context<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T, MainError>;
+}
+
+impl<T, E: Into<Box<dyn Error>>> Context<T> for Result<T, E> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T, MainError> {
+        self.map_err(|e| MainError {
+            context: Some(context.into()),
+            source: e.into(),
+        })
+    }
+}
+
+impl From<Box<dyn Error>> for MainError {
+    fn from(source: Box<dyn Error>) -> Self {
+        Self { context: None, source }
+    }
+}
+
+// `main` prints the errors it returns with `Debug`
+impl fmt::Debug for MainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.context {
+            Some(context) => write!(f, "{}: {}", context, self.source),
+            None => write!(f, "{}", self.source),
+        }
+    }
+}
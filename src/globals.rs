@@ -1,59 +1,44 @@
-use enigo::{Enigo, KeyboardControllable};
+use std::convert::TryFrom;
+
 use num_derive::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
+use crate::input::bindings::{Key, PlayerBindings};
+use crate::input::InputSink;
+
 pub enum MemoryAddress {
     GameAddress = 0x140000000,
-
-    /*
-    PlayerOneBaseAddress = 0x00342B780,
-    PlayerTwoBaseAddress = 0x00342E750,
-
-    PlayerCharacterID = 0xD8,
-    PlayerCoordinateOffsetX = 0x160,
-    PlayerCoordinateOffsetY = 0x164,
-    PlayerCoordinateOffsetZ = 0x168,
-    PlayerThrowingOther = 0x2EC,
-
-    /// Current combo count performed on the player
-    OtherComboCount = 0x2F8,
-    PlayerHealthOffset = 0x73C,
-    InputAttack = 0x16BC,
-    InputDirection = 0x16C0,
-
-    RoundCount = 0x00340EEB4,
-    RoundTimerInFrames = 0x00340ECE4,
-    ThrowTimer = 0x00342EE30,
-
-    PlayerOneFacing = 0x00341D6A0,
-    PlayerTwoFacing = 0x00341D6A4,
-    */
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// Health every character starts a round with
+pub const MAX_HEALTH: u32 = 170;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Player {
     One,
     Two,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Side {
     Left,
     Right
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Facing {
     Left,
     Right,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Face {
     Down,
     Up,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Where the feet of a grounded player point, relative to the opponent
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Feet {
     Facing,
     Away
@@ -61,7 +46,7 @@ pub enum Feet {
 
 pub type GroundState = (Face, Feet);
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive, Serialize, Deserialize)]
 pub enum Wakeup {
     Standing,
     Crouching,
@@ -96,7 +81,18 @@ pub enum Wakeup {
     Special
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Part of an attack the player is in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttackPhase {
+    /// Before the first frame that can hit
+    Startup,
+    /// Frames that can hit
+    Active,
+    /// After the last frame that can hit
+    Recovery,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum InputDirection {
     Neutral = 1 << 5,
 
@@ -110,7 +106,7 @@ pub enum InputDirection {
     UpBack = 1 << 7,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum InputButton {
     None = 0,
     One = 512,
@@ -149,7 +145,7 @@ pub enum InputButton {
     RagePlusOnePlusTwoPlusThreePlusFour = 15872,
 }
 
-#[derive(Clone, Copy, FromPrimitive, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive, Serialize, Deserialize)]
 pub enum Character {
     Paul = 0,
     Law,
@@ -315,105 +311,33 @@ impl InputButton {
         }
     }
 
-    pub fn to_input_key(&self, player: &Player) -> &'static str {
-        match player {
-            Player::One => match self {
-                InputButton::None => "",
-                InputButton::One => "u",
-                InputButton::Two => "i",
-                InputButton::Three => "j",
-                InputButton::Four => "k",
-                InputButton::OnePlusTwo => "ui",
-                InputButton::OnePlusThree => "uj",
-                InputButton::OnePlusFour => "uk",
-                InputButton::TwoPlusThree => "ij",
-                InputButton::TwoPlusFour => "ik",
-                InputButton::ThreePlusFour => "jk",
-                InputButton::OnePlusTwoPlusThree => "uij",
-                InputButton::OnePlusTwoPlusFour => "uik",
-                InputButton::OnePlusThreePlusFour => "ujk",
-                InputButton::TwoPlusThreePlusFour => "ijk",
-                InputButton::OnePlusTwoPlusThreePlusFour => "uijk",
-                InputButton::Rage => "o",
-
-                InputButton::RagePlusOne => "ou",
-                InputButton::RagePlusTwo => "oi",
-                InputButton::RagePlusThree => "oj",
-                InputButton::RagePlusFour => "ok",
-                InputButton::RagePlusOnePlusTwo => "oui",
-                InputButton::RagePlusOnePlusThree => "ouj",
-                InputButton::RagePlusOnePlusFour => "ouk",
-                InputButton::RagePlusTwoPlusThree => "oij",
-                InputButton::RagePlusTwoPlusFour => "oik",
-                InputButton::RagePlusThreePlusFour => "ojk",
-                InputButton::RagePlusOnePlusTwoPlusThree => "ouij",
-                InputButton::RagePlusOnePlusTwoPlusFour => "ouik",
-                InputButton::RagePlusOnePlusThreePlusFour => "oujk",
-                InputButton::RagePlusTwoPlusThreePlusFour => "oijk",
-                InputButton::RagePlusOnePlusTwoPlusThreePlusFour => "ouijk",
-            },
-
-            Player::Two => match self {
-                InputButton::None => "",
-                InputButton::One => "7",
-                InputButton::Two => "8",
-                InputButton::Three => "5",
-                InputButton::Four => "6",
-                InputButton::OnePlusTwo => "78",
-                InputButton::OnePlusThree => "75",
-                InputButton::OnePlusFour => "76",
-                InputButton::TwoPlusThree => "85",
-                InputButton::TwoPlusFour => "86",
-                InputButton::ThreePlusFour => "56",
-                InputButton::OnePlusTwoPlusThree => "785",
-                InputButton::OnePlusTwoPlusFour => "786",
-                InputButton::OnePlusThreePlusFour => "756",
-                InputButton::TwoPlusThreePlusFour => "856",
-                InputButton::OnePlusTwoPlusThreePlusFour => "7856",
-                InputButton::Rage => "-",
-
-                InputButton::RagePlusOne => "-7",
-                InputButton::RagePlusTwo => "-8",
-                InputButton::RagePlusThree => "-5",
-                InputButton::RagePlusFour => "-6",
-                InputButton::RagePlusOnePlusTwo => "-78",
-                InputButton::RagePlusOnePlusThree => "-75",
-                InputButton::RagePlusOnePlusFour => "-76",
-                InputButton::RagePlusTwoPlusThree => "-85",
-                InputButton::RagePlusTwoPlusFour => "-86",
-                InputButton::RagePlusThreePlusFour => "-56",
-                InputButton::RagePlusOnePlusTwoPlusThree => "-785",
-                InputButton::RagePlusOnePlusTwoPlusFour => "-786",
-                InputButton::RagePlusOnePlusThreePlusFour => "-756",
-                InputButton::RagePlusTwoPlusThreePlusFour => "-856",
-                InputButton::RagePlusOnePlusTwoPlusThreePlusFour => "-7856",
-            },
-        }
+    /// Keys to hold for this button combination
+    pub fn to_input_keys(&self, bindings: &PlayerBindings) -> Vec<Key> {
+        let value = *self as usize;
+
+        [
+            (InputButton::Rage, bindings.rage),
+            (InputButton::One, bindings.one),
+            (InputButton::Two, bindings.two),
+            (InputButton::Three, bindings.three),
+            (InputButton::Four, bindings.four),
+        ]
+        .iter()
+        .filter(|(button, _)| value & *button as usize != 0)
+        .map(|(_, key)| *key)
+        .collect()
     }
 
-    pub fn input_attack(&self, player: Player, previous_button: Option<InputButton>) {
-        let input_keys = self.to_input_key(&player);
-        let mut enigo = Enigo::new();
-
-        if previous_button.is_some() {
-            let prev = previous_button.unwrap();
-
-            if input_keys != prev.to_input_key(&player) {
-                for key in prev.to_input_key(&player).chars() {
-                    enigo.key_up(enigo::Key::Layout(key));
-                }
-
-                for key in self.to_input_key(&player).chars() {
-                    enigo.key_down(enigo::Key::Layout(key));
-                }
-
-                return;
-            }
-        } else {
-            for key in self.to_input_key(&player).chars() {
-                enigo.key_down(enigo::Key::Layout(key));
-            }
-        }
+    /// Presses this button combination, releasing whatever part of
+    /// `previous_button` isn't part of it
+    pub fn input_attack<S: InputSink>(
+        &self,
+        sink: &mut S,
+        bindings: &PlayerBindings,
+        previous_button: Option<InputButton>,
+    ) {
+        let previous_keys = previous_button.map_or(Vec::new(), |prev| prev.to_input_keys(bindings));
+        sink.swap_keys(&previous_keys, &self.to_input_keys(bindings));
     }
 }
 
@@ -432,95 +356,56 @@ impl InputDirection {
         }
     }
 
-    /// Takes player and player side to calculate which button(s) to press
-    pub fn to_input_key(&self, player: &Player, side: Player) -> &'static str {
-        match player {
-            Player::One => match side {
-                Player::One => match self {
-                    InputDirection::Neutral => "",
-                    InputDirection::Back => "a",
-                    InputDirection::DownBack => "as",
-                    InputDirection::Down => "s",
-                    InputDirection::DownForward => "sd",
-                    InputDirection::Forward => "d",
-                    InputDirection::UpForward => "wd",
-                    InputDirection::Up => "w",
-                    InputDirection::UpBack => "wa",
-                },
-                Player::Two => match self {
-                    InputDirection::Neutral => "",
-                    InputDirection::Back => "d",
-                    InputDirection::DownBack => "ds",
-                    InputDirection::Down => "s",
-                    InputDirection::DownForward => "sa",
-                    InputDirection::Forward => "a",
-                    InputDirection::UpForward => "wa",
-                    InputDirection::Up => "w",
-                    InputDirection::UpBack => "wd",
-                },
-            },
-            Player::Two => match side {
-                Player::One => match self {
-                    InputDirection::Neutral => "",
-                    InputDirection::Back => "3",
-                    InputDirection::DownBack => "23",
-                    InputDirection::Down => "2",
-                    InputDirection::DownForward => "24",
-                    InputDirection::Forward => "4",
-                    InputDirection::UpForward => "14",
-                    InputDirection::Up => "1",
-                    InputDirection::UpBack => "13",
-                },
-                Player::Two => match self {
-                    InputDirection::Neutral => "",
-                    InputDirection::Back => "4",
-                    InputDirection::DownBack => "24",
-                    InputDirection::Down => "2",
-                    InputDirection::DownForward => "23",
-                    InputDirection::Forward => "3",
-                    InputDirection::UpForward => "13",
-                    InputDirection::Up => "1",
-                    InputDirection::UpBack => "14",
-                },
-            },
-        }
+    /// Keys to hold for this direction, where `side` is the side of the
+    /// screen the player is on: `Player::One` for the left side, so that
+    /// forward is to the right, and `Player::Two` for the right side
+    pub fn to_input_keys(&self, bindings: &PlayerBindings, side: Player) -> Vec<Key> {
+        let (back, forward) = match side {
+            Player::One => (bindings.left, bindings.right),
+            Player::Two => (bindings.right, bindings.left),
+        };
+
+        let keys: &[Key] = match self {
+            InputDirection::Neutral => &[],
+            InputDirection::Back => &[back],
+            InputDirection::DownBack => &[bindings.down, back],
+            InputDirection::Down => &[bindings.down],
+            InputDirection::DownForward => &[bindings.down, forward],
+            InputDirection::Forward => &[forward],
+            InputDirection::UpForward => &[bindings.up, forward],
+            InputDirection::Up => &[bindings.up],
+            InputDirection::UpBack => &[bindings.up, back],
+        };
+
+        keys.to_vec()
     }
 
-    pub fn input_direction(
+    /// Holds this direction on `side`, releasing whatever part of the
+    /// previous direction isn't part of it. Directions held across a side
+    /// switch are pressed again with the keys of the new side.
+    pub fn input_direction<S: InputSink>(
         &self,
-        player: Player,
+        sink: &mut S,
+        bindings: &PlayerBindings,
         side: Player,
         previous_side: Option<Player>,
         previous_direction: Option<InputDirection>,
     ) {
-        let input_keys = self.to_input_key(&player, side);
-        let mut enigo = Enigo::new();
-
-        if previous_direction.is_some() && previous_side.is_some() {
-            let prev = previous_direction
-                .unwrap()
-                .to_input_key(&player, previous_side.unwrap());
-
-            if prev != input_keys {
-                for key in prev.chars() {
-                    enigo.key_up(enigo::Key::Layout(key));
-                }
-
-                for key in input_keys.chars() {
-                    enigo.key_down(enigo::Key::Layout(key));
-                }
-            }
-        } else {
-            for key in input_keys.chars() {
-                enigo.key_down(enigo::Key::Layout(key));
-            }
-        }
+        let previous_keys = match (previous_direction, previous_side) {
+            (Some(direction), Some(side)) => direction.to_input_keys(bindings, side),
+            _ => Vec::new(),
+        };
+
+        sink.swap_keys(&previous_keys, &self.to_input_keys(bindings, side));
     }
 }
 
-impl From<usize> for InputDirection {
-    fn from(value: usize) -> InputDirection {
-        match value {
+/// Fails with the raw value when it isn't a known direction
+impl TryFrom<usize> for InputDirection {
+    type Error = usize;
+
+    fn try_from(value: usize) -> Result<InputDirection, usize> {
+        let direction = match value {
             32 => InputDirection::Neutral,
             0 => InputDirection::Neutral,
 
@@ -533,17 +418,19 @@ impl From<usize> for InputDirection {
             256 => InputDirection::Up,
             128 => InputDirection::UpBack,
 
-            _ => panic!(format!(
-                "Received unknown value for input direction with value {}",
-                value
-            )),
-        }
+            _ => return Err(value),
+        };
+
+        Ok(direction)
     }
 }
 
-impl From<usize> for InputButton {
-    fn from(value: usize) -> InputButton {
-        match value {
+/// Fails with the raw value when it isn't a known button combination
+impl TryFrom<usize> for InputButton {
+    type Error = usize;
+
+    fn try_from(value: usize) -> Result<InputButton, usize> {
+        let button = match value {
             0 => InputButton::None,
             512 => InputButton::One,
             1024 => InputButton::Two,
@@ -579,8 +466,10 @@ impl From<usize> for InputButton {
             15360 => InputButton::RagePlusTwoPlusThreePlusFour,
             15872 => InputButton::RagePlusOnePlusTwoPlusThreePlusFour,
 
-            _ => panic!(format!("Unknown input button encountered: {}", value)),
-        }
+            _ => return Err(value),
+        };
+
+        Ok(button)
     }
 }
 
@@ -0,0 +1,306 @@
+//! Keyboard keys bound to each player's inputs.
+//!
+//! Keys are written as a single character, such as `"w"` or `"-"`, or by
+//! name for keys that don't type one:
+//!
+//! ```text
+//! up down left right             arrow keys
+//! f1 ... f12                     function keys
+//! space enter tab escape backspace
+//! shift control alt
+//! home end pageup pagedown delete
+//! ```
+//!
+//! Names are case insensitive, and so are letters, since they're the same
+//! key on the keyboard.
+
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::globals::Player;
+
+/// Key layout bundled into the executable, matching Tekken's default keyboard config
+pub const DEFAULT_BINDINGS: &str = include_str!("../../config/bindings.toml");
+
+/// Single key of the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    /// Key typing this character, letters always being lowercase
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Function key from F1 to F12
+    F(u8),
+    Space,
+    Enter,
+    Tab,
+    Escape,
+    Backspace,
+    Shift,
+    Control,
+    Alt,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Delete,
+}
+
+/// Keys written by name, along with that name
+const NAMED_KEYS: [(&str, Key); 17] = [
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("space", Key::Space),
+    ("enter", Key::Enter),
+    ("tab", Key::Tab),
+    ("escape", Key::Escape),
+    ("backspace", Key::Backspace),
+    ("shift", Key::Shift),
+    ("control", Key::Control),
+    ("alt", Key::Alt),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("delete", Key::Delete),
+];
+
+/// Keys bound to the inputs of a single player
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerBindings {
+    pub up: Key,
+    pub down: Key,
+    pub left: Key,
+    pub right: Key,
+    pub one: Key,
+    pub two: Key,
+    pub three: Key,
+    pub four: Key,
+    pub rage: Key,
+}
+
+/// Keys bound to both players
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyBindings {
+    pub player_one: PlayerBindings,
+    pub player_two: PlayerBindings,
+}
+
+#[derive(Debug)]
+pub enum BindingErrors {
+    /// Key bound to both of the named inputs
+    Conflict(Key, String, String),
+    /// Neither a single character nor the name of a key
+    UnknownKey(String),
+}
+
+impl std::fmt::Display for BindingErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingErrors::Conflict(key, first, second) => write!(
+                f,
+                "Key \"{}\" is bound to both {} and {}",
+                key, first, second
+            ),
+            BindingErrors::UnknownKey(key) => write!(f, "Unknown key {:?}", key),
+        }
+    }
+}
+
+impl Error for BindingErrors {}
+
+impl FromStr for Key {
+    type Err = BindingErrors;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let mut chars = key.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(Key::Char(c.to_ascii_lowercase()));
+        }
+
+        let name = key.to_ascii_lowercase();
+        if let Some((_, named)) = NAMED_KEYS.iter().find(|(n, _)| *n == name) {
+            return Ok(*named);
+        }
+
+        match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            Some(n) if (1..=12).contains(&n) => Ok(Key::F(n)),
+            _ => Err(BindingErrors::UnknownKey(key.to_string())),
+        }
+    }
+}
+
+/// Writes the key the way bindings files spell it
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Key::Char(c) => write!(f, "{}", c),
+            Key::F(n) => write!(f, "f{}", n),
+            key => {
+                let (name, _) = NAMED_KEYS.iter().find(|(_, named)| named == key).unwrap();
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl PlayerBindings {
+    /// Every binding along with its name
+    pub fn keys(&self) -> [(&'static str, Key); 9] {
+        [
+            ("up", self.up),
+            ("down", self.down),
+            ("left", self.left),
+            ("right", self.right),
+            ("one", self.one),
+            ("two", self.two),
+            ("three", self.three),
+            ("four", self.four),
+            ("rage", self.rage),
+        ]
+    }
+}
+
+impl KeyBindings {
+    /// Loads bindings from a TOML file and checks them for conflicts
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::from_toml_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<Self, Box<dyn Error>> {
+        let bindings: Self = toml::from_str(contents)?;
+        bindings.validate()?;
+
+        Ok(bindings)
+    }
+
+    pub fn player(&self, player: Player) -> &PlayerBindings {
+        match player {
+            Player::One => &self.player_one,
+            Player::Two => &self.player_two,
+        }
+    }
+
+    /// Fails on the first key bound to more than one input, since both
+    /// players share the same keyboard
+    pub fn validate(&self) -> Result<(), BindingErrors> {
+        let bindings: Vec<(String, Key)> = [("player_one", &self.player_one), ("player_two", &self.player_two)]
+            .iter()
+            .flat_map(|(player, bindings)| {
+                bindings
+                    .keys()
+                    .iter()
+                    .map(move |(name, key)| (format!("{}.{}", player, name), *key))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        for (index, (name, key)) in bindings.iter().enumerate() {
+            if let Some((other, _)) = bindings[..index].iter().find(|(_, other)| other == key) {
+                return Err(BindingErrors::Conflict(*key, other.clone(), name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self::from_toml_str(DEFAULT_BINDINGS).expect("Bundled key bindings are invalid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Default layout with `replacements` applied line by line
+    fn layout(replacements: &[(&str, &str)]) -> String {
+        replacements
+            .iter()
+            .fold(DEFAULT_BINDINGS.to_string(), |layout, (from, to)| layout.replacen(from, to, 1))
+    }
+
+    #[test]
+    fn parses_characters_and_key_names() {
+        assert_eq!("w".parse::<Key>().unwrap(), Key::Char('w'));
+        assert_eq!("W".parse::<Key>().unwrap(), Key::Char('w'));
+        assert_eq!("-".parse::<Key>().unwrap(), Key::Char('-'));
+        assert_eq!("Left".parse::<Key>().unwrap(), Key::Left);
+        assert_eq!("pagedown".parse::<Key>().unwrap(), Key::PageDown);
+        assert_eq!("F12".parse::<Key>().unwrap(), Key::F(12));
+
+        for unknown in &["", "f0", "f13", "leftarrow", "ww"] {
+            assert!(unknown.parse::<Key>().is_err(), "{:?} parsed", unknown);
+        }
+    }
+
+    #[test]
+    fn keys_are_written_the_way_they_are_read() {
+        let bindings = KeyBindings::from_toml_str(&layout(&[
+            ("up = \"1\"", "up = \"up\""),
+            ("down = \"2\"", "down = \"down\""),
+            ("left = \"3\"", "left = \"left\""),
+            ("right = \"4\"", "right = \"right\""),
+            ("rage = \"-\"", "rage = \"f5\""),
+        ]))
+        .unwrap();
+        assert_eq!(bindings.player_two.left, Key::Left);
+        assert_eq!(bindings.player_two.rage, Key::F(5));
+
+        let written = toml::to_string(&bindings).unwrap();
+        let read = KeyBindings::from_toml_str(&written).unwrap();
+        assert_eq!(read.player_two.keys(), bindings.player_two.keys());
+        assert_eq!(read.player_one.keys(), bindings.player_one.keys());
+    }
+
+    #[test]
+    fn rejects_keys_bound_twice_by_one_player() {
+        // Letters are the same key whatever their case
+        let error = KeyBindings::from_toml_str(&layout(&[("down = \"s\"", "down = \"W\"")])).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Key \"w\" is bound to both player_one.up and player_one.down"
+        );
+    }
+
+    #[test]
+    fn rejects_keys_shared_by_both_players() {
+        let error = KeyBindings::from_toml_str(&layout(&[
+            ("right = \"d\"", "right = \"right\""),
+            ("right = \"4\"", "right = \"Right\""),
+        ]))
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Key \"right\" is bound to both player_one.right and player_two.right"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(KeyBindings::from_toml_str(&layout(&[("one = \"u\"", "one = \"numpad1\"")])).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use enigo::{Enigo, Key, KeyboardControllable};
+
+use super::{InputSink, Key as BoundKey};
+
+/// Presses keys on the real keyboard
+pub struct KeyboardSink {
+    enigo: Enigo,
+}
+
+impl KeyboardSink {
+    pub fn new() -> Self {
+        Self {
+            enigo: Enigo::new(),
+        }
+    }
+}
+
+impl Default for KeyboardSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputSink for KeyboardSink {
+    fn key_down(&mut self, key: BoundKey) {
+        self.enigo.key_down(enigo_key(key));
+    }
+
+    fn key_up(&mut self, key: BoundKey) {
+        self.enigo.key_up(enigo_key(key));
+    }
+}
+
+fn enigo_key(key: BoundKey) -> Key {
+    match key {
+        BoundKey::Char(c) => Key::Layout(c),
+        BoundKey::Up => Key::UpArrow,
+        BoundKey::Down => Key::DownArrow,
+        BoundKey::Left => Key::LeftArrow,
+        BoundKey::Right => Key::RightArrow,
+        BoundKey::F(n) => [
+            Key::F1,
+            Key::F2,
+            Key::F3,
+            Key::F4,
+            Key::F5,
+            Key::F6,
+            Key::F7,
+            Key::F8,
+            Key::F9,
+            Key::F10,
+            Key::F11,
+            Key::F12,
+        ][n as usize - 1],
+        BoundKey::Space => Key::Space,
+        BoundKey::Enter => Key::Return,
+        BoundKey::Tab => Key::Tab,
+        BoundKey::Escape => Key::Escape,
+        BoundKey::Backspace => Key::Backspace,
+        BoundKey::Shift => Key::Shift,
+        BoundKey::Control => Key::Control,
+        BoundKey::Alt => Key::Alt,
+        BoundKey::Home => Key::Home,
+        BoundKey::End => Key::End,
+        BoundKey::PageUp => Key::PageUp,
+        BoundKey::PageDown => Key::PageDown,
+        BoundKey::Delete => Key::Delete,
+    }
+}
@@ -0,0 +1,39 @@
+//! Sending inputs to the game.
+//!
+//! Everything that presses keys goes through an [`InputSink`], so the same
+//! playback and bot logic can drive the real keyboard or be recorded in
+//! memory.
+
+pub mod bindings;
+pub mod keyboard;
+pub mod notation;
+pub mod recording;
+pub mod scheduler;
+#[cfg(test)]
+pub(crate) mod testing;
+
+pub use bindings::{Key, KeyBindings, PlayerBindings};
+pub use keyboard::KeyboardSink;
+pub use notation::{Sequence, Timing};
+pub use recording::{KeyEvent, RecordingSink};
+
+/// Destination of key presses
+pub trait InputSink {
+    fn key_down(&mut self, key: Key);
+    fn key_up(&mut self, key: Key);
+
+    /// Called before the inputs of each game frame are pressed
+    fn begin_frame(&mut self, _frame: u128) {}
+
+    /// Releases the keys of `previous` missing from `next` and presses the
+    /// keys of `next` that weren't already held
+    fn swap_keys(&mut self, previous: &[Key], next: &[Key]) {
+        for key in previous.iter().filter(|key| !next.contains(key)) {
+            self.key_up(*key);
+        }
+
+        for key in next.iter().filter(|key| !previous.contains(key)) {
+            self.key_down(*key);
+        }
+    }
+}
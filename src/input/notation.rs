@@ -0,0 +1,490 @@
+//! Tekken move notation.
+//!
+//! Parses notation such as `d/f+1, 2`, `f,F+2`, `ws4` or `b+1+2` into a
+//! [`Sequence`] of inputs timed in frames. Directions are relative to where
+//! the player faces, like the recorded inputs of a capture.
+//!
+//! ```text
+//! f b u d n            directions, and neutral
+//! d/f df d/b db ...    diagonals, with or without the slash
+//! F D/F ...            uppercase directions are held
+//! 1 2 3 4 RD           buttons, combined with `+`
+//! 1* b+1*              `*` holds the whole input
+//! ,                    next input after a short gap
+//! ~                    next input immediately after, also used for slides
+//! :                    next input immediately after, flagged as a just frame
+//! ws fc wr ss ssl ssr  stances entered before the input
+//! qcf qcb hcf hcb cd   motions ending in the direction of the input
+//! ```
+//!
+//! Whitespace is ignored, so `FC d/f+1` and `fcdf1` read the same.
+
+use std::convert::TryFrom;
+use std::error::Error;
+
+use crate::globals::{InputButton, InputDirection, Player};
+use crate::input::scheduler::{Inputs, Schedule};
+
+/// Frame counts used to lay out a sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    /// Frames a tapped input is held for
+    pub tap: u128,
+    /// Neutral frames in between inputs separated by `,`
+    pub gap: u128,
+    /// Frames a held input is held for
+    pub hold: u128,
+    /// Frames down is held for to reach full crouch
+    pub crouch: u128,
+    /// Frames forward is held for to start running
+    pub run: u128,
+}
+
+/// Inputs pressed on a frame of a sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedInput {
+    /// Frame the inputs are pressed on, counted from the start of the sequence
+    pub frame: u128,
+    pub direction: InputDirection,
+    pub button: InputButton,
+    /// Number of frames the inputs are held for
+    pub hold: u128,
+    /// Input written after `:`, which only works on one exact frame of the
+    /// previous move
+    pub just_frame: bool,
+}
+
+/// Inputs of a parsed notation, in the order they're pressed
+#[derive(Debug, Clone, Default)]
+pub struct Sequence {
+    inputs: Vec<TimedInput>,
+}
+
+#[derive(Debug)]
+pub enum NotationErrors {
+    /// Nothing to press in between two separators of the notation
+    MissingInput(String),
+    /// Text that isn't valid notation, along with the input it's part of
+    Unexpected(String, String),
+}
+
+impl std::fmt::Display for NotationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotationErrors::MissingInput(notation) => write!(f, "Missing an input in {:?}", notation),
+            NotationErrors::Unexpected(text, input) => {
+                write!(f, "Unexpected {:?} in input {:?}", text, input)
+            }
+        }
+    }
+}
+
+impl Error for NotationErrors {}
+
+/// How an input follows the previous one
+#[derive(Clone, Copy, PartialEq)]
+enum Separator {
+    Start,
+    Gap,
+    Immediate,
+    JustFrame,
+}
+
+#[derive(Clone, Copy)]
+enum Stance {
+    WhileStanding,
+    FullCrouch,
+    WhileRunning,
+    Sidestep(InputDirection),
+}
+
+/// Single input in between separators
+struct Input {
+    stance: Option<Stance>,
+    motion: Option<&'static [InputDirection]>,
+    direction: Option<InputDirection>,
+    held_direction: bool,
+    button: InputButton,
+    /// Whole input is held rather than tapped
+    held: bool,
+}
+
+const STANCES: &[(&str, Stance)] = &[
+    ("ssl", Stance::Sidestep(InputDirection::Up)),
+    ("ssr", Stance::Sidestep(InputDirection::Down)),
+    ("ss", Stance::Sidestep(InputDirection::Up)),
+    ("ws", Stance::WhileStanding),
+    ("fc", Stance::FullCrouch),
+    ("wr", Stance::WhileRunning),
+];
+
+const MOTIONS: &[(&str, &[InputDirection])] = &[
+    (
+        "qcf",
+        &[InputDirection::Down, InputDirection::DownForward, InputDirection::Forward],
+    ),
+    (
+        "qcb",
+        &[InputDirection::Down, InputDirection::DownBack, InputDirection::Back],
+    ),
+    (
+        "hcf",
+        &[
+            InputDirection::Back,
+            InputDirection::DownBack,
+            InputDirection::Down,
+            InputDirection::DownForward,
+            InputDirection::Forward,
+        ],
+    ),
+    (
+        "hcb",
+        &[
+            InputDirection::Forward,
+            InputDirection::DownForward,
+            InputDirection::Down,
+            InputDirection::DownBack,
+            InputDirection::Back,
+        ],
+    ),
+    (
+        "cd",
+        &[
+            InputDirection::Forward,
+            InputDirection::Neutral,
+            InputDirection::Down,
+            InputDirection::DownForward,
+        ],
+    ),
+];
+
+/// Longer spellings come first so `d/f` isn't read as `d`
+const DIRECTIONS: &[(&str, InputDirection)] = &[
+    ("d/f", InputDirection::DownForward),
+    ("d/b", InputDirection::DownBack),
+    ("u/f", InputDirection::UpForward),
+    ("u/b", InputDirection::UpBack),
+    ("df", InputDirection::DownForward),
+    ("db", InputDirection::DownBack),
+    ("uf", InputDirection::UpForward),
+    ("ub", InputDirection::UpBack),
+    ("f", InputDirection::Forward),
+    ("b", InputDirection::Back),
+    ("u", InputDirection::Up),
+    ("d", InputDirection::Down),
+    ("n", InputDirection::Neutral),
+];
+
+const BUTTONS: &[(&str, InputButton)] = &[
+    ("1", InputButton::One),
+    ("2", InputButton::Two),
+    ("3", InputButton::Three),
+    ("4", InputButton::Four),
+    ("rd", InputButton::Rage),
+    ("ra", InputButton::Rage),
+];
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            tap: 2,
+            gap: 2,
+            hold: 20,
+            crouch: 10,
+            run: 20,
+        }
+    }
+}
+
+impl Sequence {
+    /// Parses `notation` with the default timing
+    pub fn parse(notation: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse_with(notation, &Timing::default())
+    }
+
+    pub fn parse_with(notation: &str, timing: &Timing) -> Result<Self, Box<dyn Error>> {
+        let mut sequence = Self::default();
+        let mut end = 0;
+
+        for (separator, text) in split(notation) {
+            if text.trim().is_empty() {
+                return Err(NotationErrors::MissingInput(notation.to_string()).into());
+            }
+
+            let input = parse_input(text)?;
+            let frame = match separator {
+                Separator::Start => 0,
+                Separator::Gap => end + timing.gap,
+                Separator::Immediate | Separator::JustFrame => end,
+            };
+
+            end = sequence.expand(&input, frame, timing, separator == Separator::JustFrame);
+        }
+
+        Ok(sequence)
+    }
+
+    pub fn inputs(&self) -> &[TimedInput] {
+        &self.inputs
+    }
+
+    /// Frame after the last input is released
+    pub fn end(&self) -> u128 {
+        self.inputs.iter().map(|i| i.frame + i.hold).max().unwrap_or(0)
+    }
+
+    /// Schedule pressing the sequence from `side`, see
+    /// [`InputDirection::to_input_keys`]
+    pub fn schedule(&self, side: Player) -> Schedule {
+        let mut schedule = Schedule::new();
+        for input in &self.inputs {
+            let inputs = Inputs {
+                button: input.button,
+                direction: input.direction,
+                side,
+            };
+            schedule.hold(input.frame, inputs, input.hold);
+        }
+
+        schedule
+    }
+
+    /// Lays out `input` starting on `frame`, returning the frame after it's
+    /// released
+    fn expand(&mut self, input: &Input, mut frame: u128, timing: &Timing, just_frame: bool) -> u128 {
+        let mut direction = input.direction;
+
+        match input.stance {
+            Some(Stance::WhileStanding) => {
+                frame = self.push(frame, InputDirection::Down, InputButton::None, timing.crouch);
+            }
+            Some(Stance::FullCrouch) => {
+                frame = self.push(frame, InputDirection::Down, InputButton::None, timing.crouch);
+                direction = direction.or(Some(InputDirection::Down));
+            }
+            Some(Stance::WhileRunning) => {
+                frame = self.push(frame, InputDirection::Forward, InputButton::None, timing.tap) + timing.gap;
+                frame = self.push(frame, InputDirection::Forward, InputButton::None, timing.run);
+                direction = direction.or(Some(InputDirection::Forward));
+            }
+            Some(Stance::Sidestep(step)) => {
+                frame = self.push(frame, step, InputButton::None, timing.tap) + timing.gap;
+            }
+            None => (),
+        }
+
+        if let Some((last, steps)) = input.motion.and_then(|motion| motion.split_last()) {
+            for step in steps {
+                frame = self.push(frame, *step, InputButton::None, timing.tap);
+            }
+            direction = Some(*last);
+        }
+
+        let direction = direction.unwrap_or(InputDirection::Neutral);
+        let press = self.inputs.len();
+
+        let end = if input.held_direction && !input.held {
+            let end = self.push(frame, direction, InputButton::None, timing.hold);
+            if input.button != InputButton::None {
+                self.push(frame, direction, input.button, timing.tap);
+            }
+            end
+        } else {
+            let hold = if input.held { timing.hold } else { timing.tap };
+            self.push(frame, direction, input.button, hold)
+        };
+
+        for input in &mut self.inputs[press..] {
+            input.just_frame = just_frame;
+        }
+
+        end
+    }
+
+    fn push(&mut self, frame: u128, direction: InputDirection, button: InputButton, hold: u128) -> u128 {
+        self.inputs.push(TimedInput {
+            frame,
+            direction,
+            button,
+            hold,
+            just_frame: false,
+        });
+
+        frame + hold
+    }
+}
+
+/// Inputs of `notation` along with the separator in front of each
+fn split(notation: &str) -> Vec<(Separator, &str)> {
+    let mut parts = Vec::new();
+    let mut separator = Separator::Start;
+    let mut begin = 0;
+
+    for (index, c) in notation.char_indices() {
+        let next = match c {
+            ',' => Separator::Gap,
+            '~' => Separator::Immediate,
+            ':' => Separator::JustFrame,
+            _ => continue,
+        };
+
+        parts.push((separator, &notation[begin..index]));
+        separator = next;
+        begin = index + c.len_utf8();
+    }
+
+    parts.push((separator, &notation[begin..]));
+    parts
+}
+
+fn parse_input(text: &str) -> Result<Input, NotationErrors> {
+    let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let unexpected = |rest: &str| NotationErrors::Unexpected(rest.to_string(), text.trim().to_string());
+    let mut rest = compact.as_str();
+
+    let stance = take(&mut rest, STANCES).map(|(_, stance)| stance);
+    let motion = take(&mut rest, MOTIONS).map(|(_, motion)| motion);
+    let (direction, held_direction) = match motion {
+        Some(_) => (None, false),
+        None => match take(&mut rest, DIRECTIONS) {
+            Some((spelling, direction)) => (Some(direction), spelling.chars().any(|c| c.is_ascii_uppercase())),
+            None => (None, false),
+        },
+    };
+
+    let mut buttons = 0;
+    while !rest.is_empty() && !rest.starts_with('*') {
+        let before = rest;
+        if direction.is_some() || motion.is_some() || buttons != 0 {
+            rest = rest.strip_prefix('+').unwrap_or(rest);
+        }
+
+        let (_, button) = take(&mut rest, BUTTONS).ok_or_else(|| unexpected(before))?;
+        buttons |= button as usize;
+    }
+
+    let held = rest.starts_with('*');
+    if held {
+        rest = &rest[1..];
+    }
+    if !rest.is_empty() {
+        return Err(unexpected(rest));
+    }
+    if stance.is_none() && motion.is_none() && direction.is_none() && buttons == 0 {
+        return Err(unexpected(&compact));
+    }
+
+    let button = InputButton::try_from(buttons).map_err(|_| unexpected(&compact))?;
+
+    Ok(Input {
+        stance,
+        motion,
+        direction,
+        held_direction,
+        button,
+        held,
+    })
+}
+
+/// Removes the first of `words` that `rest` starts with, ignoring case, and
+/// returns it as spelled in `rest`
+fn take<'r, T: Copy>(rest: &mut &'r str, words: &[(&str, T)]) -> Option<(&'r str, T)> {
+    for (word, value) in words {
+        if let Some(spelling) = rest.get(..word.len()) {
+            if spelling.eq_ignore_ascii_case(word) {
+                *rest = &rest[word.len()..];
+                return Some((spelling, *value));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(frame: u128, direction: InputDirection, button: InputButton, hold: u128) -> TimedInput {
+        TimedInput {
+            frame,
+            direction,
+            button,
+            hold,
+            just_frame: false,
+        }
+    }
+
+    fn parse(notation: &str) -> Vec<TimedInput> {
+        Sequence::parse(notation).unwrap().inputs().to_vec()
+    }
+
+    #[test]
+    fn strings_are_separated_by_gaps() {
+        assert_eq!(
+            parse("d/f+1, 2"),
+            vec![
+                input(0, InputDirection::DownForward, InputButton::One, 2),
+                input(4, InputDirection::Neutral, InputButton::Two, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn uppercase_directions_are_held_under_the_button() {
+        let sequence = Sequence::parse("f,F+2").unwrap();
+
+        assert_eq!(
+            sequence.inputs(),
+            &[
+                input(0, InputDirection::Forward, InputButton::None, 2),
+                input(4, InputDirection::Forward, InputButton::None, 20),
+                input(4, InputDirection::Forward, InputButton::Two, 2),
+            ]
+        );
+        assert_eq!(sequence.end(), 24);
+    }
+
+    #[test]
+    fn motions_end_in_the_direction_of_the_input() {
+        assert_eq!(
+            parse("cd1"),
+            vec![
+                input(0, InputDirection::Forward, InputButton::None, 2),
+                input(2, InputDirection::Neutral, InputButton::None, 2),
+                input(4, InputDirection::Down, InputButton::None, 2),
+                input(6, InputDirection::DownForward, InputButton::One, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn just_frames_follow_immediately() {
+        let mut just_frame = input(2, InputDirection::Neutral, InputButton::Two, 2);
+        just_frame.just_frame = true;
+
+        assert_eq!(
+            parse("b+1:2"),
+            vec![input(0, InputDirection::Back, InputButton::One, 2), just_frame]
+        );
+    }
+
+    #[test]
+    fn stances_and_spacing_are_read_the_same_in_any_case() {
+        assert_eq!(parse("FC d/f+1"), parse("fcdf1"));
+        assert_eq!(parse("b+1+2*"), vec![input(0, InputDirection::Back, InputButton::OnePlusTwo, 20)]);
+    }
+
+    #[test]
+    fn rejects_invalid_notation() {
+        for notation in &["1,,2", "", "f,", "f+5", "x", "f+1 2*3"] {
+            assert!(Sequence::parse(notation).is_err(), "{:?} parsed", notation);
+        }
+
+        let error = Sequence::parse("1,,2").unwrap_err();
+        assert!(error.downcast_ref::<NotationErrors>().map_or(false, |e| match e {
+            NotationErrors::MissingInput(_) => true,
+            _ => false,
+        }));
+    }
+}
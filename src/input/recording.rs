@@ -0,0 +1,88 @@
+use std::collections::BTreeSet;
+
+use super::{InputSink, Key};
+
+/// Key press or release recorded by a `RecordingSink`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyEvent {
+    Down(Key),
+    Up(Key),
+}
+
+/// Keeps key events in memory instead of sending them anywhere
+#[derive(Default)]
+pub struct RecordingSink {
+    /// Events along with the frame they were sent on
+    events: Vec<(u128, KeyEvent)>,
+    held: BTreeSet<Key>,
+    frame: u128,
+}
+
+impl RecordingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> &[(u128, KeyEvent)] {
+        &self.events
+    }
+
+    /// Events sent on `frame`
+    pub fn events_on(&self, frame: u128) -> impl Iterator<Item = KeyEvent> + '_ {
+        self.events
+            .iter()
+            .filter(move |(f, _)| *f == frame)
+            .map(|(_, event)| *event)
+    }
+
+    /// Keys currently held down
+    pub fn held(&self) -> &BTreeSet<Key> {
+        &self.held
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.held.clear();
+    }
+}
+
+impl InputSink for RecordingSink {
+    fn key_down(&mut self, key: Key) {
+        self.held.insert(key);
+        self.events.push((self.frame, KeyEvent::Down(key)));
+    }
+
+    fn key_up(&mut self, key: Key) {
+        self.held.remove(&key);
+        self.events.push((self.frame, KeyEvent::Up(key)));
+    }
+
+    fn begin_frame(&mut self, frame: u128) {
+        self.frame = frame;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_events_on_the_frame_they_were_sent() {
+        let mut sink = RecordingSink::new();
+        sink.key_down(Key::Char('a'));
+        sink.begin_frame(3);
+        sink.swap_keys(&[Key::Char('a')], &[Key::Char('a'), Key::Down]);
+        sink.begin_frame(5);
+        sink.key_up(Key::Char('a'));
+
+        assert_eq!(
+            sink.events(),
+            &[(0, KeyEvent::Down(Key::Char('a'))), (3, KeyEvent::Down(Key::Down)), (5, KeyEvent::Up(Key::Char('a')))]
+        );
+        assert_eq!(sink.events_on(3).collect::<Vec<_>>(), vec![KeyEvent::Down(Key::Down)]);
+        assert_eq!(sink.held().iter().collect::<Vec<_>>(), vec![&Key::Down]);
+
+        sink.clear();
+        assert!(sink.events().is_empty() && sink.held().is_empty());
+    }
+}
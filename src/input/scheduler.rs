@@ -0,0 +1,415 @@
+//! Frame accurate input scheduling.
+//!
+//! A [`Schedule`] lists the inputs to hold on each frame, counted from the
+//! frame the schedule starts on. The [`Scheduler`] watches a frame counter,
+//! normally the game's `round_frame`, and switches keys exactly when the
+//! counter reaches a frame where the inputs change. Frames are never
+//! guessed from wall time, so playback can't drift from the game.
+
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use super::{InputSink, PlayerBindings};
+use crate::globals::{InputButton, InputDirection, Player};
+use crate::memory::MemoryModel;
+
+/// Length of a single game frame
+pub const FRAME_DURATION: f64 = 1.0 / 60.0;
+/// Time in between reads of the frame counter
+pub const POLL_INTERVAL_MS: u64 = 1;
+
+/// Source of the current frame number
+pub trait FrameClock {
+    fn frame(&self) -> Result<u128, Box<dyn Error>>;
+}
+
+/// Reads the frame counter of the running round
+pub struct GameClock<'a, M> {
+    memory: &'a M,
+}
+
+/// Counts frames from wall time, for when the game can't be read
+pub struct WallClock {
+    start: Instant,
+}
+
+/// Inputs held on a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Inputs {
+    pub button: InputButton,
+    pub direction: InputDirection,
+    /// Side of the screen directions are pressed from, see
+    /// [`InputDirection::to_input_keys`]
+    pub side: Player,
+}
+
+/// Inputs held for a number of frames
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    /// Frame the inputs are pressed on
+    pub frame: u128,
+    pub inputs: Inputs,
+    /// Number of frames the inputs are held for
+    pub hold: u128,
+}
+
+/// Inputs to press over time. Later steps take priority where steps
+/// overlap, and nothing is held where no step applies.
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    steps: Vec<Step>,
+}
+
+/// Transition applied after its frame had already passed
+#[derive(Debug, Clone, Copy)]
+pub struct Late {
+    pub frame: u128,
+    /// Number of frames it was applied after its frame
+    pub by: u128,
+}
+
+/// How closely a schedule was followed
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleReport {
+    /// Transitions applied on their frame
+    pub on_time: usize,
+    pub late: Vec<Late>,
+    /// Frames of transitions that were skipped because a later transition
+    /// was already due by the time they could be applied
+    pub missed: Vec<u128>,
+}
+
+/// Presses a schedule into a sink in step with a frame clock
+pub struct Scheduler<'a, C> {
+    clock: C,
+    bindings: &'a PlayerBindings,
+}
+
+impl<'a, M: MemoryModel> GameClock<'a, M> {
+    pub fn new(memory: &'a M) -> Self {
+        Self { memory }
+    }
+}
+
+impl<'a, M: MemoryModel> FrameClock for GameClock<'a, M> {
+    fn frame(&self) -> Result<u128, Box<dyn Error>> {
+        Ok(self.memory.round_frame()?.into())
+    }
+}
+
+impl WallClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameClock for WallClock {
+    fn frame(&self) -> Result<u128, Box<dyn Error>> {
+        Ok((self.start.elapsed().as_secs_f64() / FRAME_DURATION) as u128)
+    }
+}
+
+impl Inputs {
+    /// Nothing held
+    pub const NEUTRAL: Inputs = Inputs {
+        button: InputButton::None,
+        direction: InputDirection::Neutral,
+        side: Player::One,
+    };
+
+    pub fn is_neutral(&self) -> bool {
+        self.button == InputButton::None && self.direction == InputDirection::Neutral
+    }
+
+    /// Switches the keys held for `previous` over to these inputs
+    pub fn press<S: InputSink>(&self, sink: &mut S, bindings: &PlayerBindings, previous: Option<Inputs>) {
+        self.button
+            .input_attack(sink, bindings, previous.map(|p| p.button));
+        self.direction.input_direction(
+            sink,
+            bindings,
+            self.side,
+            previous.map(|p| p.side),
+            previous.map(|p| p.direction),
+        );
+    }
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Holds `inputs` for `hold` frames starting on `frame`
+    pub fn hold(&mut self, frame: u128, inputs: Inputs, hold: u128) -> &mut Self {
+        self.steps.push(Step {
+            frame,
+            inputs,
+            hold,
+        });
+        self
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Frame after the last input is released
+    pub fn end(&self) -> u128 {
+        self.steps.iter().map(|s| s.frame + s.hold).max().unwrap_or(0)
+    }
+
+    /// Inputs held on `frame`
+    pub fn inputs_at(&self, frame: u128) -> Inputs {
+        self.steps
+            .iter()
+            .rev()
+            .find(|s| s.frame <= frame && frame < s.frame + s.hold)
+            .map_or(Inputs::NEUTRAL, |s| s.inputs)
+    }
+
+    /// Frames where the held inputs change along with the inputs held from
+    /// then on, ending with everything released
+    pub fn timeline(&self) -> Vec<(u128, Inputs)> {
+        let mut frames: Vec<u128> = self
+            .steps
+            .iter()
+            .flat_map(|s| vec![s.frame, s.frame + s.hold])
+            .collect();
+        frames.sort();
+        frames.dedup();
+
+        let mut timeline: Vec<(u128, Inputs)> = Vec::new();
+        for frame in frames {
+            let inputs = self.inputs_at(frame);
+            let previous = timeline.last().map_or(Inputs::NEUTRAL, |(_, i)| *i);
+
+            if inputs != previous && !(inputs.is_neutral() && previous.is_neutral()) {
+                timeline.push((frame, inputs));
+            }
+        }
+
+        timeline
+    }
+}
+
+impl<'a, C: FrameClock> Scheduler<'a, C> {
+    pub fn new(clock: C, bindings: &'a PlayerBindings) -> Self {
+        Self { clock, bindings }
+    }
+
+    /// Presses `schedule` into `sink`, starting on the current frame of the
+    /// clock. Stops early, releasing every key, if the frame counter goes
+    /// back down because a new round started. Keys are released as well
+    /// when reading the clock fails, before the error is returned.
+    pub fn run<S: InputSink>(&self, schedule: &Schedule, sink: &mut S) -> Result<ScheduleReport, Box<dyn Error>> {
+        let mut report = ScheduleReport::default();
+        let mut held: Option<Inputs> = None;
+        let mut reached = 0;
+
+        let followed = self.follow(&schedule.timeline(), sink, &mut report, &mut held, &mut reached);
+
+        if let Some(held) = held.filter(|held| !held.is_neutral()) {
+            sink.begin_frame(reached);
+            Inputs::NEUTRAL.press(sink, self.bindings, Some(held));
+        }
+
+        followed.map(|()| report)
+    }
+
+    /// Presses every transition of `timeline` on its frame, keeping track
+    /// of the inputs left `held` and of the last frame `reached`
+    fn follow<S: InputSink>(
+        &self,
+        timeline: &[(u128, Inputs)],
+        sink: &mut S,
+        report: &mut ScheduleReport,
+        held: &mut Option<Inputs>,
+        reached: &mut u128,
+    ) -> Result<(), Box<dyn Error>> {
+        let start = self.clock.frame()?;
+        let mut last_seen = None;
+        let mut next = 0;
+
+        while next < timeline.len() {
+            let now = self.clock.frame()?;
+            if now < start || last_seen.is_some_and(|last| now < last) {
+                println!("The frame counter went back down, stopping the schedule");
+                report.missed.extend(timeline[next..].iter().map(|(frame, _)| *frame));
+                break;
+            }
+
+            if last_seen == Some(now) {
+                std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+                continue;
+            }
+            last_seen = Some(now);
+
+            let frame = now - start;
+            *reached = frame;
+            let mut due = None;
+            while next < timeline.len() && timeline[next].0 <= frame {
+                if let Some((skipped, _)) = due {
+                    report.missed.push(skipped);
+                }
+                due = Some(timeline[next]);
+                next += 1;
+            }
+
+            if let Some((due_frame, inputs)) = due {
+                if due_frame == frame {
+                    report.on_time += 1;
+                } else {
+                    report.late.push(Late {
+                        frame: due_frame,
+                        by: frame - due_frame,
+                    });
+                }
+
+                sink.begin_frame(frame);
+                inputs.press(sink, self.bindings, *held);
+                *held = Some(inputs);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::input::testing::{bindings, clock};
+    use crate::input::{Key, KeyEvent, RecordingSink};
+
+    /// Clock that counts up one frame per read and fails after `fail_after` reads
+    struct FailingClock {
+        reads: Cell<u128>,
+        fail_after: u128,
+    }
+
+    impl FrameClock for FailingClock {
+        fn frame(&self) -> Result<u128, Box<dyn Error>> {
+            let read = self.reads.get();
+            self.reads.set(read + 1);
+
+            if read >= self.fail_after {
+                return Err("lost the game".into());
+            }
+            Ok(read)
+        }
+    }
+
+    fn inputs(direction: InputDirection, button: InputButton) -> Inputs {
+        Inputs {
+            button,
+            direction,
+            side: Player::One,
+        }
+    }
+
+    #[test]
+    fn records_a_schedule_frame_by_frame() {
+        let mut schedule = Schedule::new();
+        schedule
+            .hold(0, inputs(InputDirection::Forward, InputButton::One), 2)
+            .hold(4, inputs(InputDirection::Down, InputButton::None), 3);
+
+        let bindings = bindings();
+        let mut sink = RecordingSink::new();
+        let report = Scheduler::new(clock(&[100, 100, 101, 101, 102, 103, 104, 105, 106, 107]), &bindings)
+            .run(&schedule, &mut sink)
+            .unwrap();
+
+        assert_eq!(
+            sink.events(),
+            &[
+                (0, KeyEvent::Down(Key::Char('u'))),
+                (0, KeyEvent::Down(Key::Char('d'))),
+                (2, KeyEvent::Up(Key::Char('u'))),
+                (2, KeyEvent::Up(Key::Char('d'))),
+                (4, KeyEvent::Down(Key::Char('s'))),
+                (7, KeyEvent::Up(Key::Char('s'))),
+            ]
+        );
+        assert!(sink.held().is_empty());
+        assert_eq!(report.on_time, 4);
+        assert!(report.late.is_empty() && report.missed.is_empty());
+    }
+
+    #[test]
+    fn reports_late_and_missed_transitions() {
+        let mut schedule = Schedule::new();
+        schedule
+            .hold(0, inputs(InputDirection::Back, InputButton::None), 1)
+            .hold(1, inputs(InputDirection::Neutral, InputButton::Two), 1)
+            .hold(2, inputs(InputDirection::Up, InputButton::None), 2);
+
+        let bindings = bindings();
+        let mut sink = RecordingSink::new();
+        let report = Scheduler::new(clock(&[10, 10, 13, 14]), &bindings)
+            .run(&schedule, &mut sink)
+            .unwrap();
+
+        // Frame 3 is the first read after frame 0, so the presses on
+        // frames 1 and 2 are due together and only the last is made
+        assert_eq!(report.on_time, 2);
+        assert_eq!(report.missed, vec![1]);
+        assert_eq!(report.late.len(), 1);
+        assert_eq!((report.late[0].frame, report.late[0].by), (2, 1));
+        assert_eq!(sink.events_on(3).collect::<Vec<_>>(), vec![KeyEvent::Up(Key::Char('a')), KeyEvent::Down(Key::Char('w'))]);
+        assert!(sink.held().is_empty());
+    }
+
+    #[test]
+    fn stops_when_the_frame_counter_goes_back_down() {
+        let mut schedule = Schedule::new();
+        schedule.hold(0, inputs(InputDirection::Forward, InputButton::None), 10);
+
+        let bindings = bindings();
+        let mut sink = RecordingSink::new();
+        let report = Scheduler::new(clock(&[50, 50, 51, 2]), &bindings)
+            .run(&schedule, &mut sink)
+            .unwrap();
+
+        assert_eq!(report.missed, vec![10]);
+        assert_eq!(sink.events().last(), Some(&(1, KeyEvent::Up(Key::Char('d')))));
+        assert!(sink.held().is_empty());
+    }
+
+    #[test]
+    fn releases_held_keys_when_the_clock_fails() {
+        let bindings = bindings();
+        let inputs = Inputs {
+            button: InputButton::Two,
+            direction: InputDirection::DownBack,
+            side: Player::One,
+        };
+
+        let mut schedule = Schedule::new();
+        schedule.hold(0, inputs, 30);
+
+        let clock = FailingClock {
+            reads: Cell::new(0),
+            fail_after: 3,
+        };
+        let mut sink = RecordingSink::new();
+        let error = Scheduler::new(clock, &bindings).run(&schedule, &mut sink).unwrap_err();
+
+        assert_eq!(error.to_string(), "lost the game");
+        assert!(sink.events().contains(&(1, KeyEvent::Down(Key::Char('i')))));
+        // Released on the last frame the clock could be read on
+        assert_eq!(sink.events().last().map(|(frame, _)| *frame), Some(2));
+        assert!(sink.held().is_empty());
+    }
+}
@@ -0,0 +1,43 @@
+//! Fixtures shared by the input tests.
+
+use std::cell::Cell;
+use std::error::Error;
+
+use super::scheduler::FrameClock;
+use super::{Key, PlayerBindings};
+
+/// Clock reading out a fixed list of frames, one per read
+pub struct ScriptedClock {
+    frames: Vec<u128>,
+    reads: Cell<usize>,
+}
+
+impl FrameClock for ScriptedClock {
+    fn frame(&self) -> Result<u128, Box<dyn Error>> {
+        let read = self.reads.get();
+        self.reads.set(read + 1);
+        self.frames.get(read).copied().ok_or_else(|| "the clock ran out of frames".into())
+    }
+}
+
+pub fn clock(frames: &[u128]) -> ScriptedClock {
+    ScriptedClock {
+        frames: frames.to_vec(),
+        reads: Cell::new(0),
+    }
+}
+
+/// WASD for directions and UIJKL for buttons
+pub fn bindings() -> PlayerBindings {
+    PlayerBindings {
+        up: Key::Char('w'),
+        down: Key::Char('s'),
+        left: Key::Char('a'),
+        right: Key::Char('d'),
+        one: Key::Char('u'),
+        two: Key::Char('i'),
+        three: Key::Char('j'),
+        four: Key::Char('k'),
+        rage: Key::Char('l'),
+    }
+}
@@ -4,36 +4,56 @@
 //! That data will then be used to analyze the game state and provide
 //! suggestions for optimal moves, similar to stockfish in chess.
  
+pub mod analysis;
+pub mod capture;
 mod errors;
 pub mod globals;
+pub mod input;
 pub mod memory;
+pub mod replay;
 pub mod states;
 pub mod util;
 
 use std::env;
-use std::thread;
+use std::error::Error;
+use std::path::Path;
 
-use clap::{self, App, Arg};
-use read_process_memory::*;
+use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
+use read_process_memory::Pid;
+#[cfg(not(target_os = "linux"))]
+use read_process_memory::TryIntoProcessHandle;
 
-use crate::memory::models::season_three::V3Dot33;
+#[cfg(target_os = "linux")]
+use crate::memory::backends::ProcMemBackend;
+#[cfg(not(target_os = "linux"))]
+use crate::memory::backends::ProcessBackend;
+use crate::memory::backends::{SnapshotRecorder, SnapshotReplay};
+use crate::memory::models::{season_three, OffsetModel, OffsetTable};
+use crate::memory::scanner::Scanner;
+use crate::memory::version::{BuildId, VersionRegistry};
+use crate::analysis::FrameDataTable;
+use crate::errors::{Context, MainError};
+use crate::globals::Player;
+use crate::input::scheduler::{GameClock, WallClock};
+use crate::input::{KeyBindings, KeyboardSink};
+use crate::memory::MemoryBackend;
+use crate::replay::{Library, Playback, Replay, Transcript};
+use crate::states::game_state::GameState;
 use crate::states::loop_state::LoopState;
+use crate::states::output::{CaptureFormat, OutputConfig};
 
 /// Name of the executable to search for
 pub const EXECUTABLE_NAME: &'static str = "TekkenGame-Win64-Shipping.exe";
 
-fn main() {
+/// What to do once attached to the game memory
+enum Command {
+    Capture,
+    Scan,
+}
+
+fn main() -> Result<(), MainError> {
     let args = App::new("TEKKEN God Prime Trainer")
         .version(&clap::crate_version!()[..])
-        .arg(
-            Arg::with_name("reverse")
-                .short("s")
-                .long("reverse")
-                .value_name("REVERSE")
-                .help("Reverses the player's side")
-                .required(false)
-                .takes_value(false),
-        )
         .arg(
             Arg::with_name("output")
                 .short("o")
@@ -41,31 +61,517 @@ fn main() {
                 .value_name("out")
                 .help("Sets the output directory of the data")
                 .takes_value(true)
-                .conflicts_with_all(&["replay", "replay_path", "reverse"])
                 .required(true),
         )
+        .arg(
+            Arg::with_name("name")
+                .long("name")
+                .value_name("TEMPLATE")
+                .help(
+                    "Sets the file name of captured rounds. Supports {p1}, {p2}, {p1_name}, \
+                     {p2_name}, {round}, {timestamp} and {uuid}",
+                )
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Sets the file format of captured matches")
+                .possible_values(&["binary", "json"])
+                .default_value("binary")
+                .takes_value(true),
+        )
+        .arg(offsets_arg())
+        .arg(
+            Arg::with_name("offsets_dir")
+                .long("offsets-dir")
+                .value_name("DIR")
+                .help("Adds every offset table in the directory to the supported game versions")
+                .takes_value(true)
+                .conflicts_with("offsets")
+                .required(false),
+        )
+        .arg(snapshots_arg())
+        .subcommand(
+            SubCommand::with_name("scan")
+                .about("Relocates memory offsets by scanning the game module for byte signatures")
+                .arg(offsets_arg())
+                .arg(snapshots_arg())
+                .arg(
+                    Arg::with_name("write")
+                        .long("write")
+                        .value_name("FILE")
+                        .help("Writes the relocated offset table to a TOML file")
+                        .takes_value(true)
+                        .required(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Records the game memory read over a round into a snapshot file for --snapshots")
+                .arg(
+                    Arg::with_name("output")
+                        .value_name("OUTPUT")
+                        .help("Snapshot file to write")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("frames")
+                        .long("frames")
+                        .value_name("FRAMES")
+                        .help("Number of frames to record once a round starts")
+                        .default_value("600"),
+                )
+                .arg(offsets_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("convert")
+                .about("Converts captured matches between the binary and JSON formats")
+                .arg(
+                    Arg::with_name("input")
+                        .value_name("INPUT")
+                        .help("Capture or JSON file to convert")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .value_name("OUTPUT")
+                        .help("File to write, as a capture if it ends in .tkcp and as JSON otherwise")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("play")
+                .about("Presses the inputs of a captured round, e.g. against the training mode dummy")
+                .arg(
+                    Arg::with_name("input")
+                        .value_name("FILE")
+                        .help("Capture or JSON file to play back")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("round")
+                        .long("round")
+                        .value_name("ROUND")
+                        .help("Round number to play, defaults to the first captured round")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("player")
+                        .long("player")
+                        .value_name("PLAYER")
+                        .help("Player whose inputs are played back")
+                        .possible_values(&["1", "2"])
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::with_name("as")
+                        .long("as")
+                        .value_name("PLAYER")
+                        .help("Player whose keys are pressed, defaults to the played back player")
+                        .possible_values(&["1", "2"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("bindings")
+                        .long("bindings")
+                        .value_name("FILE")
+                        .help("Loads key bindings from a TOML file instead of the default keyboard layout")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("delay")
+                        .long("delay")
+                        .value_name("SECONDS")
+                        .help("Time to switch over to the game before playback starts")
+                        .default_value("3"),
+                )
+                .arg(offsets_arg())
+                .arg(
+                    Arg::with_name("no_sync")
+                        .long("no-sync")
+                        .help("Times frames from the system clock instead of the game's frame counter"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("transcribe")
+                .about("Writes out the inputs of a capture in Tekken notation")
+                .arg(
+                    Arg::with_name("input")
+                        .value_name("FILE")
+                        .help("Capture or JSON file to transcribe")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("round")
+                        .long("round")
+                        .value_name("ROUND")
+                        .help("Round number to transcribe, defaults to every round")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("player")
+                        .long("player")
+                        .value_name("PLAYER")
+                        .help("Player to transcribe, defaults to both")
+                        .possible_values(&["1", "2"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("timings")
+                        .long("timings")
+                        .help("Lists every press on its own line along with the frame it starts on"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("framedata")
+                .about("Measures frame advantage on block and hit of every move in captured rounds")
+                .arg(
+                    Arg::with_name("input")
+                        .value_name("INPUT")
+                        .help("Capture or JSON file, or a directory of them")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .value_name("OUTPUT")
+                        .help("Frame data table to write, as JSON if it ends in .json and CSV otherwise")
+                        .required(true),
+                ),
+        )
+        .setting(AppSettings::SubcommandsNegateReqs)
         .get_matches();
 
-    println!("Starting in capture mode...");
-    let mut process_id = util::pid();
+    if let ("convert", Some(convert_args)) = args.subcommand() {
+        let input = convert_args.value_of("input").unwrap();
+        let output = convert_args.value_of("output").unwrap();
+
+        capture::json::convert(input, output).context(format!("Failed to convert {}", input))?;
+
+        println!("Converted {} to {}", input, output);
+        return Ok(());
+    }
+
+    if let ("snapshot", Some(snapshot_args)) = args.subcommand() {
+        return record_snapshots(snapshot_args).context("Recording snapshots failed");
+    }
+
+    if let ("play", Some(play_args)) = args.subcommand() {
+        return play(play_args).context("Playback failed");
+    }
 
-    // Try every 10 seconds to get the Tekken PID
-    while let Err(pid) = process_id {
+    if let ("transcribe", Some(transcribe_args)) = args.subcommand() {
+        return transcribe(transcribe_args).context("Transcribing failed");
+    }
+
+    if let ("framedata", Some(framedata_args)) = args.subcommand() {
+        return framedata(framedata_args).context("Measuring frame data failed");
+    }
+
+    let (command, args) = match args.subcommand() {
+        ("scan", Some(scan_args)) => (Command::Scan, scan_args),
+        _ => (Command::Capture, &args),
+    };
+
+    if let Some(snapshots) = args.value_of("snapshots") {
+        println!("Starting in offline mode...");
+        let replay = SnapshotReplay::load(snapshots).context("Failed to load memory snapshot file")?;
+
+        return match command {
+            Command::Capture => capture(replay, args).context("Capture stopped"),
+            Command::Scan => scan(&replay, args).context("Scan failed"),
+        };
+    }
+
+    match command {
+        Command::Capture => {
+            println!("Starting in capture mode...");
+
+            let mut loop_state = LoopState::new(output_config(args)?);
+            loop_state.recover();
+
+            // Reattaches whenever the game exits or restarts
+            loop_state
+                .start_live(|pid| {
+                    let backend = open_backend(pid)?;
+                    let offsets = select_offsets(&backend, args)?;
+
+                    println!("Using memory offsets for game version {}", offsets.version);
+                    Ok(OffsetModel::new(backend, offsets))
+                })
+                .context("Failed to attach to Tekken")
+        }
+        Command::Scan => {
+            let backend = open_backend(util::wait_for_pid()).context("Failed to open Tekken 7 process memory")?;
+
+            scan(&backend, args).context("Scan failed")
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_backend(pid: Pid) -> Result<ProcMemBackend, Box<dyn Error>> {
+    ProcMemBackend::open(pid).map_err(|e| e.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_backend(pid: Pid) -> Result<ProcessBackend, Box<dyn Error>> {
+    let process_handle = pid.try_into_process_handle()?;
+    Ok(ProcessBackend::new(pid, process_handle))
+}
+
+/// Records every read the capture makes of the running game, from the
+/// time it waits for a round to start until `--frames` frames into it
+fn record_snapshots(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let output = args.value_of("output").unwrap();
+    let frames: usize = args.value_of("frames").unwrap().parse()?;
+
+    let backend = open_backend(util::wait_for_pid())?;
+    let offsets = select_offsets(&backend, args)?;
+    let recorder = SnapshotRecorder::new(backend);
+
+    let mut game_state = GameState::new(OffsetModel::new(&recorder, offsets));
+    if game_state.start()? {
+        for _ in 1..frames {
+            if !game_state.advance() {
+                break;
+            }
+            game_state.update()?;
+        }
+    }
+
+    recorder.save(output)?;
+    println!("Recorded {} frames of memory into {}", recorder.frames(), output);
+    Ok(())
+}
+
+/// Plays back a captured round through the keyboard
+fn play(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let player = |value: &str| match value {
+        "2" => Player::Two,
+        _ => Player::One,
+    };
+
+    let replay = Replay::load(args.value_of("input").unwrap())?;
+    let round = match args.value_of("round") {
+        Some(round) => {
+            let round: u8 = round.parse()?;
+            replay.rounds().iter().find(|r| r.result.round == round)
+        }
+        None => replay.rounds().first(),
+    }
+    .ok_or("The round isn't part of the capture")?;
+
+    let source = player(args.value_of("player").unwrap());
+    let target = args.value_of("as").map_or(source, player);
+    let delay: f64 = args.value_of("delay").unwrap().parse()?;
+    let bindings = match args.value_of("bindings") {
+        Some(path) => KeyBindings::load(path)?,
+        None => KeyBindings::default(),
+    };
+
+    println!(
+        "Playing round {} ({} frames) in {}s...",
+        round.result.round,
+        round.states.len(),
+        delay
+    );
+    std::thread::sleep(std::time::Duration::from_secs_f64(delay));
+
+    let playback = Playback::new(round, source)
+        .controlling(target)
+        .with_bindings(bindings);
+
+    let report = if args.is_present("no_sync") {
+        playback.play(&mut KeyboardSink::new(), WallClock::new())?
+    } else {
+        let backend = open_backend(util::wait_for_pid())?;
+        let offsets = select_offsets(&backend, args)?;
+        let memory = OffsetModel::new(backend, offsets);
+
+        playback.play(&mut KeyboardSink::new(), GameClock::new(&memory))?
+    };
+
+    println!(
+        "{} transitions on time, {} late, {} missed",
+        report.on_time,
+        report.late.len(),
+        report.missed.len()
+    );
+    for late in &report.late {
+        println!("  frame {} applied {} frames late", late.frame, late.by);
+    }
+    for frame in &report.missed {
+        println!("  frame {} missed", frame);
+    }
+
+    Ok(())
+}
+
+/// Prints the inputs of a capture in notation
+fn transcribe(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let replay = Replay::load(args.value_of("input").unwrap())?;
+    let round: Option<u8> = args.value_of("round").map(|round| round.parse()).transpose()?;
+    let players: &[Player] = match args.value_of("player") {
+        Some("1") => &[Player::One],
+        Some(_) => &[Player::Two],
+        None => &[Player::One, Player::Two],
+    };
+
+    for record in replay.rounds() {
+        if round.map_or(false, |round| round != record.result.round) {
+            continue;
+        }
+
+        for player in players {
+            let transcript = Transcript::new(&record.states, *player)?;
+            let character = match record.states.first() {
+                Some(state) => state.get_player_state(*player).character(),
+                None => continue,
+            };
+            let heading = format!("Round {}, player {:?} ({:?})", record.result.round, player, character);
+
+            if args.is_present("timings") {
+                println!("{}", heading);
+                for press in transcript.presses() {
+                    println!("{:>8}  {}", press.frame, press.notation());
+                }
+            } else {
+                println!("{}: {}", heading, transcript);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the frame advantage of every move in one or more captures
+fn framedata(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let input = Path::new(args.value_of("input").unwrap());
+    let output = args.value_of("output").unwrap();
+
+    let library = if input.is_dir() {
+        Library::load_dir(input)?
+    } else {
+        Library::new(vec![Replay::load(input)?])
+    };
+
+    let mut table = FrameDataTable::new();
+    let interactions: usize = library.replays().iter().map(|replay| table.add_replay(replay)).sum();
+    table.save(output)?;
+
+    println!(
+        "Measured {} interactions of {} moves from {} captures into {}",
+        interactions,
+        table.rows().len(),
+        library.replays().len(),
+        output
+    );
+    Ok(())
+}
+
+fn offsets_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("offsets")
+        .long("offsets")
+        .value_name("FILE")
+        .help("Loads memory offsets from a TOML or JSON file instead of detecting the game version")
+        .takes_value(true)
+        .required(false)
+}
+
+fn snapshots_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("snapshots")
+        .long("snapshots")
+        .value_name("FILE")
+        .help("Reads from a recorded memory snapshot file instead of a running game")
+        .takes_value(true)
+        .required(false)
+}
+
+/// Resolves the byte signatures of an offset table and reports which failed
+fn scan<B: MemoryBackend>(backend: &B, args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let mut offsets = match args.value_of("offsets") {
+        Some(path) => OffsetTable::load(path)?,
+        None => season_three::v3_dot_33(),
+    };
+
+    let build = BuildId::detect(backend)?;
+    let scanner = Scanner::from_backend(backend, build.image_size as usize);
+    let report = scanner.relocate(&mut offsets);
+
+    println!(
+        "Scanned {} signatures for game version {} (timestamp 0x{:08X})",
+        offsets.signatures.len(),
+        offsets.version,
+        build.timestamp
+    );
+
+    for (name, offset) in &report.resolved {
+        println!("  resolved  {:<28} 0x{:X}", name, offset);
+    }
+
+    for (name, e) in &report.failed {
+        println!("  FAILED    {:<28} {}", name, e);
+    }
+
+    if let Some(path) = args.value_of("write") {
+        std::fs::write(path, offsets.to_toml_string()?)?;
+        println!("Wrote relocated offsets to {}", path);
+    }
+
+    Ok(())
+}
+
+/// Selects the offsets for the game version `backend` is reading, preferring
+/// an explicit `--offsets` file over version detection. Warns about the
+/// optional offsets the table doesn't include.
+fn select_offsets<B: MemoryBackend>(backend: &B, args: &ArgMatches) -> Result<OffsetTable, Box<dyn Error>> {
+    let offsets = match args.value_of("offsets") {
+        Some(path) => OffsetTable::load(path)?,
+        None => {
+            let mut registry = VersionRegistry::bundled();
+            if let Some(dir) = args.value_of("offsets_dir") {
+                registry.load_dir(dir)?;
+            }
+
+            registry.detect(backend)?.clone()
+        }
+    };
+
+    let missing = offsets.missing_offsets();
+    if !missing.is_empty() {
         println!(
-            "Failed to acquire process ID: {:?} - Retrying in 10s...",
-            pid
+            "Warning: the offsets for game version {} don't include {}. These fields are captured as zero, \
+             and the ground states, events and frame data relying on them are left out.",
+            offsets.version,
+            missing.join(", ")
         );
-        thread::sleep(std::time::Duration::from_secs(10));
-        process_id = util::pid();
     }
 
-    let process_handle = process_id
-        .unwrap()
-        .try_into_process_handle()
-        .expect("Failed to create Tekken 7 process handle. Please restart and try again.");
+    Ok(offsets)
+}
+
+/// Output directory and file naming of captured rounds
+fn output_config(args: &ArgMatches) -> Result<OutputConfig, Box<dyn Error>> {
+    let format = match args.value_of("format") {
+        Some("json") => CaptureFormat::Json,
+        _ => CaptureFormat::Binary,
+    };
+    let directory = args.value_of("output").ok_or("--out is required for capturing")?;
+
+    Ok(OutputConfig::new(directory, args.value_of("name"), format))
+}
+
+/// Captures from an offline backend until it runs out of frames
+fn capture<B: MemoryBackend>(backend: B, args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let offsets = select_offsets(&backend, args)?;
 
-    let mut loop_state = LoopState::<V3Dot33>::new(); //, match_replay);
+    println!("Using memory offsets for game version {}", offsets.version);
+    let mut loop_state = LoopState::new(output_config(args)?);
+    loop_state.recover();
 
     // Begin capturing
-    loop_state.start(process_handle);
+    loop_state.start(OffsetModel::new(backend, offsets))
 }
@@ -0,0 +1,140 @@
+//! Native Linux backend for Tekken running under Proton/Wine.
+//!
+//! Wine-hosted processes don't report the Windows executable as their
+//! process name, so the process is discovered through its command line
+//! and memory is read straight out of `/proc/<pid>/mem`.
+
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+use read_process_memory::Pid;
+
+use super::MemoryBackend;
+
+/// Reads memory from `/proc/<pid>/mem` of a Wine-hosted Tekken process
+pub struct ProcMemBackend {
+    pid: Pid,
+    mem: File,
+    module_base: usize,
+}
+
+impl ProcMemBackend {
+    /// Opens the memory of `pid` and resolves the base address of the game module
+    pub fn open(pid: Pid) -> io::Result<Self> {
+        let mem = File::open(format!("/proc/{}/mem", pid))?;
+        let module_base = module_base(pid, crate::EXECUTABLE_NAME)?;
+
+        Ok(Self {
+            pid,
+            mem,
+            module_base,
+        })
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+}
+
+impl MemoryBackend for ProcMemBackend {
+    fn copy(&self, address: usize, length: usize) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![0u8; length];
+        self.mem.read_exact_at(&mut buffer, address as u64)?;
+
+        Ok(buffer)
+    }
+
+    fn module_base(&self) -> usize {
+        self.module_base
+    }
+
+    fn is_alive(&self) -> bool {
+        Path::new(&format!("/proc/{}", self.pid)).exists()
+    }
+}
+
+/// Finds the PID of the process whose command line launches `executable`
+pub fn find_pid(executable: &str) -> Option<Pid> {
+    fs::read_dir("/proc")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<Pid>().ok())
+        .find(|pid| {
+            fs::read(format!("/proc/{}/cmdline", pid))
+                .is_ok_and(|cmdline| launches(&String::from_utf8_lossy(&cmdline), executable))
+        })
+}
+
+/// Resolves the lowest address `executable` is mapped at from `/proc/<pid>/maps`
+pub fn module_base(pid: Pid, executable: &str) -> io::Result<usize> {
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid))?;
+
+    lowest_mapping(&maps, executable).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not mapped into process {}", executable, pid),
+        )
+    })
+}
+
+/// Whether the NUL separated `cmdline` of a process launches `executable`.
+///
+/// Wine passes the Windows path of the executable as the first argument,
+/// so both `/` and `\` are treated as path separators.
+fn launches(cmdline: &str, executable: &str) -> bool {
+    cmdline
+        .split('\0')
+        .next()
+        .and_then(|program| program.rsplit(['/', '\\']).next())
+        == Some(executable)
+}
+
+/// Lowest start address of the lines of `maps` that map `executable`
+fn lowest_mapping(maps: &str, executable: &str) -> Option<usize> {
+    maps.lines()
+        .filter(|line| line.ends_with(executable))
+        .filter_map(|line| {
+            let range = line.split_whitespace().next()?;
+            let start = range.split('-').next()?;
+            usize::from_str_radix(start, 16).ok()
+        })
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXECUTABLE: &str = "TekkenGame-Win64-Shipping.exe";
+
+    #[test]
+    fn matches_the_program_of_wine_command_lines() {
+        let wine = "Z:\\games\\TEKKEN 7\\TekkenGame\\Binaries\\Win64\\TekkenGame-Win64-Shipping.exe\0-nosplash\0";
+        let native = "/opt/tekken/TekkenGame-Win64-Shipping.exe\0";
+
+        assert!(launches(wine, EXECUTABLE));
+        assert!(launches(native, EXECUTABLE));
+        assert!(launches("TekkenGame-Win64-Shipping.exe", EXECUTABLE));
+        // Only the program counts, not its arguments
+        assert!(!launches("/usr/bin/wine\0TekkenGame-Win64-Shipping.exe\0", EXECUTABLE));
+        assert!(!launches("/usr/bin/TekkenGame-Win64-Shipping.exe.bak\0", EXECUTABLE));
+        assert!(!launches("", EXECUTABLE));
+    }
+
+    #[test]
+    fn finds_the_lowest_mapping_of_the_module() {
+        let maps = "\
+00010000-00011000 r--p 00000000 00:00 0 
+140001000-1400f0000 r-xp 00001000 103:02 393 /games/tekken/TekkenGame-Win64-Shipping.exe
+140000000-140001000 r--p 00000000 103:02 393 /games/tekken/TekkenGame-Win64-Shipping.exe
+7f0000000000-7f0000001000 r--p 00000000 103:02 12 /usr/lib/wine/x86_64-windows/ntdll.dll
+";
+
+        assert_eq!(lowest_mapping(maps, EXECUTABLE), Some(0x1_4000_0000));
+        assert_eq!(lowest_mapping(maps, "ntdll.dll"), Some(0x7f00_0000_0000));
+        assert_eq!(lowest_mapping(maps, "missing.exe"), None);
+        assert_eq!(lowest_mapping("zzzz-1000 r--p 0 0:0 0 /x/TekkenGame-Win64-Shipping.exe", EXECUTABLE), None);
+    }
+}
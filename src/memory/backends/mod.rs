@@ -0,0 +1,66 @@
+#[cfg(target_os = "linux")]
+pub mod linux;
+pub mod process;
+pub mod snapshot;
+
+#[cfg(target_os = "linux")]
+pub use linux::ProcMemBackend;
+pub use process::ProcessBackend;
+pub use snapshot::{MemoryRegion, MemorySnapshot, SnapshotRecorder, SnapshotReplay};
+
+use crate::globals::MemoryAddress;
+
+/// Source of the raw game memory a `MemoryModel` decodes.
+///
+/// Live capture reads straight out of the Tekken process, while offline
+/// backends serve previously recorded bytes so that the capture pipeline
+/// can run without the game open.
+pub trait MemoryBackend {
+    /// Copies `length` bytes starting at `address`
+    fn copy(&self, address: usize, length: usize) -> std::io::Result<Vec<u8>>;
+
+    /// Address the game module is loaded at
+    fn module_base(&self) -> usize {
+        MemoryAddress::GameAddress as usize
+    }
+
+    /// Moves the backend onto the next frame of data.
+    /// Returns `false` once there is nothing left to read.
+    fn advance(&self) -> bool {
+        true
+    }
+
+    /// Whether the memory being read still exists. Checked after a failed
+    /// read to tell a game that exited apart from a bad address.
+    fn is_alive(&self) -> bool {
+        true
+    }
+
+    /// Whether reads follow the game in real time. Offline backends
+    /// return `false` so that callers can skip their frame pacing sleeps.
+    fn is_live(&self) -> bool {
+        true
+    }
+}
+
+impl<B: MemoryBackend> MemoryBackend for &B {
+    fn copy(&self, address: usize, length: usize) -> std::io::Result<Vec<u8>> {
+        (**self).copy(address, length)
+    }
+
+    fn module_base(&self) -> usize {
+        (**self).module_base()
+    }
+
+    fn advance(&self) -> bool {
+        (**self).advance()
+    }
+
+    fn is_alive(&self) -> bool {
+        (**self).is_alive()
+    }
+
+    fn is_live(&self) -> bool {
+        (**self).is_live()
+    }
+}
@@ -0,0 +1,35 @@
+use read_process_memory::{copy_address, Pid, ProcessHandle};
+use sysinfo::SystemExt;
+
+use super::MemoryBackend;
+
+/// Reads memory from a running Tekken 7 process
+#[derive(Clone)]
+pub struct ProcessBackend {
+    pid: Pid,
+    handle: ProcessHandle,
+}
+
+impl ProcessBackend {
+    pub fn new(pid: Pid, handle: ProcessHandle) -> Self {
+        Self { pid, handle }
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn handle(&self) -> &ProcessHandle {
+        &self.handle
+    }
+}
+
+impl MemoryBackend for ProcessBackend {
+    fn copy(&self, address: usize, length: usize) -> std::io::Result<Vec<u8>> {
+        copy_address(address, length, &self.handle)
+    }
+
+    fn is_alive(&self) -> bool {
+        sysinfo::System::new().refresh_process(self.pid as sysinfo::Pid)
+    }
+}
@@ -0,0 +1,247 @@
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::MemoryBackend;
+
+/// Contiguous block of memory captured at `address`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MemoryRegion {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// Every memory region captured during a single frame
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct MemorySnapshot {
+    pub regions: Vec<MemoryRegion>,
+}
+
+/// Replays memory from a recorded sequence of snapshots, one per frame.
+/// Reads are served from the first frame until `advance()` is called.
+pub struct SnapshotReplay {
+    frames: Vec<MemorySnapshot>,
+    frame: Cell<usize>,
+}
+
+/// Records every read made through a live backend, one snapshot per frame,
+/// so that a session can be replayed offline through `SnapshotReplay`
+pub struct SnapshotRecorder<B> {
+    backend: B,
+    frames: RefCell<Vec<MemorySnapshot>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile {
+    frames: Vec<MemorySnapshot>,
+}
+
+impl MemoryRegion {
+    pub fn new(address: usize, bytes: Vec<u8>) -> Self {
+        Self { address, bytes }
+    }
+
+    fn contains(&self, address: usize, length: usize) -> bool {
+        address >= self.address && address + length <= self.address + self.bytes.len()
+    }
+}
+
+impl MemorySnapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `bytes` at `address`, replacing any region previously captured there
+    pub fn insert(&mut self, address: usize, bytes: Vec<u8>) {
+        self.regions.retain(|r| r.address != address);
+        self.regions.push(MemoryRegion::new(address, bytes));
+    }
+
+    /// Copies `length` bytes at `address` out of the first region holding all of them
+    pub fn copy(&self, address: usize, length: usize) -> Option<Vec<u8>> {
+        self.regions
+            .iter()
+            .find(|r| r.contains(address, length))
+            .map(|r| {
+                let start = address - r.address;
+                r.bytes[start..start + length].to_vec()
+            })
+    }
+}
+
+impl SnapshotReplay {
+    pub fn new(frames: Vec<MemorySnapshot>) -> Self {
+        Self {
+            frames,
+            frame: Cell::new(0),
+        }
+    }
+
+    /// Loads a snapshot file written by `SnapshotReplay::save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        let snapshots: SnapshotFile = serde_json::from_reader(BufReader::new(file))?;
+
+        Ok(Self::new(snapshots.frames))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        save_frames(path, &self.frames)
+    }
+
+    /// Index of the frame currently being served
+    pub fn frame(&self) -> usize {
+        self.frame.get()
+    }
+
+    pub fn frames(&self) -> &[MemorySnapshot] {
+        &self.frames
+    }
+}
+
+impl MemoryBackend for SnapshotReplay {
+    fn copy(&self, address: usize, length: usize) -> io::Result<Vec<u8>> {
+        let frame = self.frame.get();
+
+        self.frames
+            .get(frame)
+            .and_then(|snapshot| snapshot.copy(address, length))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "{} bytes at 0x{:X} were not captured in frame {}",
+                        length, address, frame
+                    ),
+                )
+            })
+    }
+
+    fn advance(&self) -> bool {
+        let next = self.frame.get() + 1;
+        if next >= self.frames.len() {
+            return false;
+        }
+
+        self.frame.set(next);
+        true
+    }
+
+    fn is_live(&self) -> bool {
+        false
+    }
+}
+
+impl<B: MemoryBackend> SnapshotRecorder<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            frames: RefCell::new(vec![MemorySnapshot::new()]),
+        }
+    }
+
+    /// Number of frames recorded so far, including the one being read
+    pub fn frames(&self) -> usize {
+        self.frames.borrow().len()
+    }
+
+    /// Replays the frames recorded so far
+    pub fn replay(&self) -> SnapshotReplay {
+        SnapshotReplay::new(self.frames.borrow().clone())
+    }
+
+    /// Writes the frames recorded so far in the format `SnapshotReplay::load` reads
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        save_frames(path, &self.frames.borrow())
+    }
+}
+
+impl<B: MemoryBackend> MemoryBackend for SnapshotRecorder<B> {
+    fn copy(&self, address: usize, length: usize) -> io::Result<Vec<u8>> {
+        let bytes = self.backend.copy(address, length)?;
+
+        if let Some(snapshot) = self.frames.borrow_mut().last_mut() {
+            snapshot.insert(address, bytes.clone());
+        }
+        Ok(bytes)
+    }
+
+    fn module_base(&self) -> usize {
+        self.backend.module_base()
+    }
+
+    fn advance(&self) -> bool {
+        if !self.backend.advance() {
+            return false;
+        }
+
+        self.frames.borrow_mut().push(MemorySnapshot::new());
+        true
+    }
+
+    fn is_alive(&self) -> bool {
+        self.backend.is_alive()
+    }
+
+    fn is_live(&self) -> bool {
+        self.backend.is_live()
+    }
+}
+
+fn save_frames<P: AsRef<Path>>(path: P, frames: &[MemorySnapshot]) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::create(path)?;
+    serde_json::to_writer(
+        BufWriter::new(file),
+        &SnapshotFile {
+            frames: frames.to_vec(),
+        },
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> MemorySnapshot {
+        let mut snapshot = MemorySnapshot::new();
+        snapshot.insert(0x1000, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        snapshot.insert(0x2000, vec![9]);
+        snapshot
+    }
+
+    #[test]
+    fn reads_outside_of_the_snapshot_fail() {
+        let replay = SnapshotReplay::new(vec![snapshot()]);
+
+        assert_eq!(replay.copy(0x1004, 4).unwrap(), vec![5, 6, 7, 8]);
+        assert!(replay.copy(0x1004, 8).is_err());
+        assert!(replay.copy(0x3000, 1).is_err());
+    }
+
+    #[test]
+    fn recorded_reads_replay_frame_by_frame() {
+        let mut second = snapshot();
+        second.insert(0x2000, vec![10]);
+        let recorder = SnapshotRecorder::new(SnapshotReplay::new(vec![snapshot(), second]));
+
+        assert_eq!(recorder.copy(0x1002, 2).unwrap(), vec![3, 4]);
+        assert!(recorder.copy(0x3000, 1).is_err());
+        assert!(recorder.advance());
+        assert_eq!(recorder.copy(0x2000, 1).unwrap(), vec![10]);
+        assert!(!recorder.advance());
+        assert_eq!(recorder.frames(), 2);
+
+        let replay = recorder.replay();
+        assert_eq!(replay.copy(0x1002, 2).unwrap(), vec![3, 4]);
+        // Only what was read gets recorded
+        assert!(replay.copy(0x1000, 2).is_err());
+        assert!(replay.copy(0x2000, 1).is_err());
+        assert!(replay.advance());
+        assert_eq!(replay.copy(0x2000, 1).unwrap(), vec![10]);
+    }
+}
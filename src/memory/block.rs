@@ -0,0 +1,75 @@
+use std::io::Cursor;
+use std::mem::size_of;
+
+use byteorder::ByteOrder;
+
+use super::errors::{MemoryReadDiagnostics, MemoryReadErrors};
+use super::wrappers::CursorWrapper;
+
+/// Copy of a player's memory structure taken in a single read, so that
+/// every field decoded from it comes from the same frame
+pub struct PlayerBlock {
+    address: usize,
+    bytes: Vec<u8>,
+}
+
+impl PlayerBlock {
+    pub fn new(address: usize, bytes: Vec<u8>) -> Self {
+        Self { address, bytes }
+    }
+
+    /// Address the block was copied from
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Decodes the value of type `T` stored `offset` bytes into the block.
+    /// Fails with `ShortRead` when the value doesn't fit in the block.
+    pub fn read<E, T>(&self, offset: usize) -> Result<T, Box<dyn std::error::Error>>
+    where
+        E: ByteOrder,
+        T: CursorWrapper,
+    {
+        let length = size_of::<T::Item>();
+        let bytes = self.bytes.get(offset..offset + length).ok_or_else(|| {
+            // The field ends past the bytes copied into the block
+            MemoryReadErrors::ShortRead(
+                offset + length,
+                self.bytes.len(),
+                MemoryReadDiagnostics::new(self.address + offset, "PlayerBlock::read(&self)".into()),
+            )
+        })?;
+
+        T::read::<E>(Cursor::new(bytes.to_vec())).map(|v| v.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::LittleEndian;
+
+    use super::*;
+
+    #[test]
+    fn reads_past_the_block_are_short_reads() {
+        let block = PlayerBlock::new(0x1000, vec![1, 0, 0, 0, 2, 0]);
+
+        assert_eq!((block.len(), block.is_empty()), (6, false));
+        assert_eq!(block.read::<LittleEndian, u32>(0).unwrap(), 1);
+        assert_eq!(block.read::<LittleEndian, u16>(4).unwrap(), 2);
+
+        let error = block.read::<LittleEndian, u32>(4).unwrap_err();
+        match error.downcast_ref::<MemoryReadErrors>() {
+            Some(MemoryReadErrors::ShortRead(8, 6, diag)) => assert_eq!(diag.memory_address(), 0x1004),
+            _ => panic!("expected a short read, got {}", error),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use crate::globals::Player;
 use std::error::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MemoryReadDiagnostics {
     memory_address: usize,
     method_name: String,
@@ -14,11 +14,60 @@ impl MemoryReadDiagnostics {
             method_name,
         }
     }
+
+    pub fn memory_address(&self) -> usize {
+        self.memory_address
+    }
+
+    pub fn method_name(&self) -> &str {
+        &self.method_name
+    }
 }
 
 #[derive(Debug)]
 pub enum MemoryReadErrors {
     FacingTheVoid(Player, MemoryReadDiagnostics),
+    /// The game process exited or can no longer be read
+    ProcessGone(MemoryReadDiagnostics),
+    /// Reason the backend failed to copy memory
+    ReadFailed(String, MemoryReadDiagnostics),
+    /// Number of bytes requested and number of bytes actually read
+    ShortRead(usize, usize, MemoryReadDiagnostics),
+    /// Why the running game can't be read with any known offsets
+    UnsupportedVersion(String, MemoryReadDiagnostics),
+    /// Hop index of a pointer chain that couldn't be followed, and why
+    PointerChainBroken(usize, String, MemoryReadDiagnostics),
+}
+
+impl MemoryReadErrors {
+    pub fn diagnostics(&self) -> &MemoryReadDiagnostics {
+        match self {
+            MemoryReadErrors::FacingTheVoid(_, diag)
+            | MemoryReadErrors::ProcessGone(diag)
+            | MemoryReadErrors::ReadFailed(_, diag)
+            | MemoryReadErrors::ShortRead(_, _, diag)
+            | MemoryReadErrors::UnsupportedVersion(_, diag)
+            | MemoryReadErrors::PointerChainBroken(_, _, diag) => diag,
+        }
+    }
+
+    /// Whether `error` means the game process is gone, as opposed to a
+    /// single read going wrong that may succeed on the next frame
+    pub fn is_process_gone(error: &(dyn Error + 'static)) -> bool {
+        matches!(
+            error.downcast_ref::<MemoryReadErrors>(),
+            Some(MemoryReadErrors::ProcessGone(_))
+        )
+    }
+
+    /// Whether `error` means no offsets are known for the running game,
+    /// which attaching again won't fix
+    pub fn is_unsupported_version(error: &(dyn Error + 'static)) -> bool {
+        matches!(
+            error.downcast_ref::<MemoryReadErrors>(),
+            Some(MemoryReadErrors::UnsupportedVersion(_, _))
+        )
+    }
 }
 
 impl std::fmt::Display for MemoryReadErrors {
@@ -27,6 +76,23 @@ impl std::fmt::Display for MemoryReadErrors {
             MemoryReadErrors::FacingTheVoid(player, diag) => {
                 write!(f, "{:?} is facing the void. Info: {:#?}", player, diag)
             }
+            MemoryReadErrors::ProcessGone(diag) => {
+                write!(f, "The Tekken process is no longer running. Info: {:#?}", diag)
+            }
+            MemoryReadErrors::ReadFailed(reason, diag) => {
+                write!(f, "Failed to read memory ({}). Info: {:#?}", reason, diag)
+            }
+            MemoryReadErrors::ShortRead(expected, actual, diag) => write!(
+                f,
+                "Read {} of {} bytes. Info: {:#?}",
+                actual, expected, diag
+            ),
+            MemoryReadErrors::UnsupportedVersion(reason, diag) => {
+                write!(f, "Unsupported game version: {}. Info: {:#?}", reason, diag)
+            }
+            MemoryReadErrors::PointerChainBroken(hop, reason, diag) => {
+                write!(f, "Pointer chain broke at hop {} ({}). Info: {:#?}", hop, reason, diag)
+            }
         }
     }
 }
@@ -1,31 +1,71 @@
+pub mod backends;
+pub mod block;
 pub mod errors;
 pub mod models;
+pub mod pointer;
+pub mod scanner;
+pub mod version;
 pub mod wrappers;
 
 use std::io::Cursor;
+use std::mem::size_of;
 
 use byteorder::{ByteOrder, LittleEndian};
-use read_process_memory::{ProcessHandle, copy_address};
 
-use crate::globals::{MemoryAddress, Player};
+pub use backends::MemoryBackend;
+pub use block::PlayerBlock;
 pub use errors::MemoryReadErrors;
+pub use pointer::PointerChain;
 pub use wrappers::CursorWrapper;
 pub use crate::globals::*;
 
+// Values of the simple move state as listed by TekkenBot (`SimpleMoveStates`
+// in MoveInfoEnums.py, GROUND_FACEUP and GROUND_FACEDOWN). They haven't been
+// checked against 3.33, which has no move state offset yet.
+
+/// Move state of a player lying face up
+pub const MOVE_STATE_FACE_UP: u32 = 12;
+/// Move state of a player lying face down
+pub const MOVE_STATE_FACE_DOWN: u32 = 13;
+
 pub trait MemoryModel {
-    fn new(handle: ProcessHandle) -> Self;
+    type Backend: MemoryBackend;
 
     fn read<E, T>(&self, address: usize, length: usize) -> Result<T, Box<dyn std::error::Error>>
     where
         E: ByteOrder,
         T: CursorWrapper,
     {
-        let data = copy_address(address, length, self.handle())
-            .expect(&format!("Failed to read memory address: 0x{:X}", address));
+        let data = self.copy_memory(address, length, "MemoryModel::read(&self)")?;
 
         T::read::<E>(Cursor::new(data)).map(|v| v.into())
     }
 
+    /// Copies memory through the backend, telling apart a game that exited
+    /// from a read that failed or came back short
+    fn copy_memory(
+        &self,
+        address: usize,
+        length: usize,
+        method_name: &str
+    ) -> Result<Vec<u8>, MemoryReadErrors> {
+        let diagnostics = || errors::MemoryReadDiagnostics::new(address, method_name.into());
+
+        let data = self.backend().copy(address, length).map_err(|e| {
+            if self.backend().is_alive() {
+                MemoryReadErrors::ReadFailed(e.to_string(), diagnostics())
+            } else {
+                MemoryReadErrors::ProcessGone(diagnostics())
+            }
+        })?;
+
+        if data.len() < length {
+            return Err(MemoryReadErrors::ShortRead(length, data.len(), diagnostics()));
+        }
+
+        Ok(data)
+    }
+
     fn read_player<E, T>(
         &self,
         address: usize,
@@ -36,35 +76,105 @@ pub trait MemoryModel {
         E: ByteOrder,
         T: CursorWrapper,
     {
-        let game_address = MemoryAddress::GameAddress as usize + self.player_base_address(player) + address;
+        let game_address = self.resolve(self.player_base_address(player))? + address;
         self.read::<E, T>(game_address, length)
     }
 
+    /// Follows a pointer chain to the address it points at
+    fn resolve(&self, chain: &PointerChain) -> Result<usize, Box<dyn std::error::Error>> {
+        chain.resolve(self.backend()).map_err(|e| e.into())
+    }
+
+    /// Copies the whole player structure in a single read.
+    /// Fields are decoded out of the block with their `*_address` offsets.
+    fn player_block(&self, player: Player) -> Result<PlayerBlock, Box<dyn std::error::Error>> {
+        let address = self.resolve(self.player_base_address(player))?;
+        let data = self.copy_memory(
+            address,
+            self.player_block_size(player),
+            "MemoryModel::player_block(&self)"
+        )?;
+
+        Ok(PlayerBlock::new(address, data))
+    }
+
+    /// Number of bytes from the player base that cover every player field
+    fn player_block_size(&self, player: Player) -> usize {
+        [
+            self.input_attack_address(player) + size_of::<u16>(),
+            self.input_direction_address(player) + size_of::<u16>(),
+            self.player_health_address(player) + size_of::<u32>(),
+            self.player_character_id_address(player) + size_of::<u16>(),
+            self.player_coordinate_x(player) + size_of::<f32>(),
+            self.player_coordinate_y(player) + size_of::<f32>(),
+            self.player_coordinate_z(player) + size_of::<f32>(),
+        ]
+        .iter()
+        .copied()
+        .chain(
+            self.player_optional_fields(player)
+                .iter()
+                .filter_map(|offset| offset.map(|offset| offset + size_of::<u32>())),
+        )
+        .max()
+        .unwrap_or(0)
+    }
+
+    /// Offsets of the optional player fields, all of them `u32`: move
+    /// state, move ID, move frame, move length, attack flags, active frames,
+    /// stance flags, rage, combo count and throwing
+    fn player_optional_fields(&self, player: Player) -> [Option<usize>; 11] {
+        [
+            self.player_move_state_address(player),
+            self.player_move_id_address(player),
+            self.player_move_frame_address(player),
+            self.player_move_length_address(player),
+            self.player_attack_flags_address(player),
+            self.player_active_start_address(player),
+            self.player_active_end_address(player),
+            self.player_stance_flags_address(player),
+            self.player_rage_address(player),
+            self.player_combo_count_address(player),
+            self.player_throwing_address(player),
+        ]
+    }
+
+    /// Frames left to break the throw in progress, or 0 without an offset
+    fn throw_timer(&self) -> Result<u32, Box<dyn std::error::Error>> {
+        match self.throw_timer_address() {
+            Some(chain) => {
+                let address = self.resolve(chain)?;
+                self.read::<LittleEndian, u32>(address, size_of::<u32>())
+            }
+            None => Ok(0),
+        }
+    }
+
     fn inputted_attack(&self, player: Player) -> Result<u16, Box<dyn std::error::Error>> {
         self.read_player::<LittleEndian, u16>(
             self.input_attack_address(player), 
             player, 
-            4)
+            size_of::<u16>())
     }
 
     fn inputted_direction(&self, player: Player) -> Result<u16, Box<dyn std::error::Error>> {
         self.read_player::<LittleEndian, u16>(
             self.input_direction_address(player), 
             player, 
-            4)
+            size_of::<u16>())
     }
 
     fn damage_received(&self, player: Player) -> Result<u32, Box<dyn std::error::Error>> {
         self.read_player::<LittleEndian, u32>(
             self.player_health_address(player), 
             player, 
-            4)
+            size_of::<u32>())
     }
 
     fn character(&self, player: Player) -> Result<Character, Box<dyn std::error::Error>> {
         self.read_player::<LittleEndian, u16>(
                 self.player_character_id_address(player), 
-                player, 4)
+                player, size_of::<u16>())
             .map(|c| num::FromPrimitive::from_u16(c).unwrap_or(Character::NotSelected))
     }
 
@@ -72,17 +182,17 @@ pub trait MemoryModel {
         let x = self.read_player::<LittleEndian, f32>(
             self.player_coordinate_x(player),
             player,
-            4,
+            size_of::<f32>(),
         );
         let y = self.read_player::<LittleEndian, f32>(
             self.player_coordinate_y(player),
             player,
-            4,
+            size_of::<f32>(),
         );
         let z = self.read_player::<LittleEndian, f32>(
             self.player_coordinate_z(player),
             player,
-            4,
+            size_of::<f32>(),
         );
 
         Ok((x?, y?, z?))
@@ -93,16 +203,9 @@ pub trait MemoryModel {
     }
 
     fn facing(&self, player: Player) -> Result<Facing, Box<dyn std::error::Error>> {
-        let facing_address = match player {
-            Player::One => {
-                MemoryAddress::GameAddress as usize + self.player_facing_address(player)
-            }
-            Player::Two => {
-                MemoryAddress::GameAddress as usize + self.player_facing_address(player)
-            }
-        };
+        let facing_address = self.resolve(self.player_facing_address(player))?;
 
-        let facing = self.read::<LittleEndian, u8>(facing_address, 4)?;
+        let facing = self.read::<LittleEndian, u8>(facing_address, size_of::<u8>())?;
 
         match facing {
             0 => Ok(Facing::Left),
@@ -117,26 +220,50 @@ pub trait MemoryModel {
         }
     }
 
+    /// How the player lies on the ground, decoded from their `block`, or
+    /// `None` while they're off the ground or the offsets don't include the
+    /// move state. Takes the facings already read for the frame.
     fn ground_state(
         &self,
-        _player: Player,
+        block: &PlayerBlock,
+        player: Player,
+        facing: Facing,
+        opponent_facing: Facing,
     ) -> Result<Option<GroundState>, Box<dyn std::error::Error>> {
-        todo!();
+        let offset = match self.player_move_state_address(player) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+
+        let face = match block.read::<LittleEndian, u32>(offset)? {
+            MOVE_STATE_FACE_UP => Face::Up,
+            MOVE_STATE_FACE_DOWN => Face::Down,
+            _ => return Ok(None),
+        };
+
+        // Grounded players keep facing the way their body points while the
+        // opponent keeps turning towards them. Lying face up the feet point
+        // the way the player faces, lying face down the head does.
+        let feet = match (face, facing != opponent_facing) {
+            (Face::Up, true) | (Face::Down, false) => Feet::Facing,
+            _ => Feet::Away,
+        };
+
+        Ok(Some((face, feet)))
     }
     
     fn round_frame(&self) -> Result<u32, Box<dyn std::error::Error>> {
-        let address =
-            MemoryAddress::GameAddress as usize + self.round_timer_address();
-        self.read::<LittleEndian, u32>(address, 4)
+        let address = self.resolve(self.round_timer_address())?;
+        self.read::<LittleEndian, u32>(address, size_of::<u32>())
             .map(|v| v.into())
     }
 
     fn round(&self) -> Result<u8, Box<dyn std::error::Error>> {
-        let address = MemoryAddress::GameAddress as usize + self.round_count_address();
-        self.read::<LittleEndian, u8>(address, 4)
+        let address = self.resolve(self.round_count_address())?;
+        self.read::<LittleEndian, u8>(address, size_of::<u8>())
     }
 
-    fn player_base_address(&self, player: Player)           -> usize;
+    fn player_base_address(&self, player: Player)           -> &PointerChain;
     fn input_attack_address(&self, player: Player)          -> usize;
     fn input_direction_address(&self, player: Player)       -> usize;
     fn player_health_address(&self, player: Player)         -> usize;
@@ -144,9 +271,21 @@ pub trait MemoryModel {
     fn player_coordinate_x(&self, player: Player)           -> usize;
     fn player_coordinate_y(&self, player: Player)           -> usize;
     fn player_coordinate_z(&self, player: Player)           -> usize;
-    fn player_facing_address(&self, player: Player)         -> usize;
-    fn round_count_address(&self)                           -> usize;
-    fn round_timer_address(&self)                           -> usize;
+    fn player_facing_address(&self, player: Player)         -> &PointerChain;
+    fn player_move_state_address(&self, player: Player)     -> Option<usize>;
+    fn player_move_id_address(&self, player: Player)        -> Option<usize>;
+    fn player_move_frame_address(&self, player: Player)     -> Option<usize>;
+    fn player_move_length_address(&self, player: Player)    -> Option<usize>;
+    fn player_attack_flags_address(&self, player: Player)   -> Option<usize>;
+    fn player_active_start_address(&self, player: Player)   -> Option<usize>;
+    fn player_active_end_address(&self, player: Player)     -> Option<usize>;
+    fn player_stance_flags_address(&self, player: Player)   -> Option<usize>;
+    fn player_rage_address(&self, player: Player)           -> Option<usize>;
+    fn player_combo_count_address(&self, player: Player)    -> Option<usize>;
+    fn player_throwing_address(&self, player: Player)       -> Option<usize>;
+    fn round_count_address(&self)                           -> &PointerChain;
+    fn round_timer_address(&self)                           -> &PointerChain;
+    fn throw_timer_address(&self)                           -> Option<&PointerChain>;
     /*
     fn character(&self, player: Player)         -> Result<crate::globals::Character, Box<dyn std::error::Error>>;
     fn damage_received(&self, player: Player)   -> Result<u32, Box<dyn std::error::Error>>;
@@ -161,74 +300,7 @@ pub trait MemoryModel {
     fn round_frame(&self)                       -> Result<u64, Box<dyn std::error::Error>>;
     fn round(&self)                             -> Result<u8, Box<dyn std::error::Error>>;
     */
-    fn handle(&self) -> &ProcessHandle;
-
-    /*
-    fn replay(&self, previous_frame_state: Option<&GameState<M>>, frame_state: &GameState<M>) {
-        if self.round_frame == self.round_frame_previous {
-            return;
-        }
-
-        let p1_input_attack = frame_state.p1_input_attack.expect("p1 input attack");
-        let p1_input_direction = frame_state.p1_input_direction.expect("p1 input direction");
-        let p1_button = globals::InputButton::from(p1_input_attack as usize);
-        let p1_direction = globals::InputDirection::from(p1_input_direction as usize);
-        let p1_facing = match frame_state.p1_facing {
-            Some(0) => globals::Player::One,
-            Some(1) => globals::Player::Two,
-            _ => panic!("Player one is facing the void"),
-        };
-
-        let p2_input_attack = frame_state.p2_input_attack.expect("p2 input attack");
-        let p2_input_direction = frame_state.p2_input_direction.expect("p2 input direction");
-        let p2_button = globals::InputButton::from(p2_input_attack as usize);
-        let p2_direction = globals::InputDirection::from(p2_input_direction as usize);
-        let p2_facing = match frame_state.p1_facing {
-            Some(0) => globals::Player::One,
-            Some(1) => globals::Player::Two,
-            _ => panic!("Player two is facing the void"),
-        };
-
-        // TODO: Figure out how to determine what side the player spawns on in online matches
-        p1_button.input_attack(
-            globals::Player::One,
-            previous_frame_state
-                .map(|prev| globals::InputButton::from(prev.p1_input_attack.unwrap() as usize))
-                .clone(),
-        );
-
-        p1_direction.input_direction(
-            globals::Player::One,
-            p1_facing.clone(),
-            previous_frame_state.map(|prev| match prev.p1_facing {
-                Some(0) => globals::Player::One,
-                Some(1) => globals::Player::Two,
-                _ => panic!("Player one is facing the void"),
-            }),
-            previous_frame_state.map(|prev| {
-                globals::InputDirection::from(prev.p1_input_direction.unwrap() as usize)
-            }),
-        );
-
-        // TODO: Figure out how to determine what side the player spawns on in online matches
-        p2_button.input_attack(
-            globals::Player::Two,
-            previous_frame_state
-                .map(|prev| globals::InputButton::from(prev.p2_input_attack.unwrap() as usize)),
-        );
-
-        p2_direction.input_direction(
-            globals::Player::Two,
-            p2_facing.clone(),
-            previous_frame_state.map(|prev| match prev.p2_facing {
-                Some(0) => globals::Player::One,
-                Some(1) => globals::Player::Two,
-                _ => panic!("Player two is facing the void"),
-            }),
-            previous_frame_state.map(|prev| {
-                globals::InputDirection::from(prev.p2_input_direction.unwrap() as usize)
-            }),
-        );
-    }
-    */
+    fn backend(&self) -> &Self::Backend;
+    /// Version of the game the offsets were written for
+    fn game_version(&self) -> &str;
 }
@@ -0,0 +1,340 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::MemoryModel;
+use crate::globals::Player;
+use crate::memory::backends::MemoryBackend;
+use crate::memory::pointer::PointerChain;
+use crate::memory::scanner::Signature;
+
+/// Every memory offset needed to read a single version of the game.
+///
+/// Tables are loaded from TOML or JSON files so that supporting a new
+/// game patch only requires shipping a new data file. JSON has no hex
+/// literals, so offsets in JSON tables are written in decimal.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OffsetTable {
+    /// Game version the offsets were taken from
+    pub version: String,
+    /// Build the offsets apply to. Tables without one are never auto-detected
+    #[serde(default)]
+    pub build: Option<BuildSignature>,
+    pub players: PlayerOffsets,
+    pub round: RoundOffsets,
+    /// Signatures used to relocate the offsets after a game patch
+    #[serde(default)]
+    pub signatures: Vec<Signature>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BuildSignature {
+    /// Link timestamp from the PE header of the game executable
+    pub timestamp: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerOffsets {
+    /// Base address of player one
+    pub player_one_base: PointerChain,
+    /// Base address of player two
+    pub player_two_base: PointerChain,
+    /// Facing of player one
+    pub player_one_facing: PointerChain,
+    /// Facing of player two
+    pub player_two_facing: PointerChain,
+
+    pub character_id: usize,
+    pub coordinate_x: usize,
+    pub coordinate_y: usize,
+    pub coordinate_z: usize,
+    pub health: usize,
+    pub input_attack: usize,
+    pub input_direction: usize,
+    /// Move state, which tells whether the player lies on the ground.
+    /// Ground states aren't captured without it
+    #[serde(default)]
+    pub move_state: Option<usize>,
+    /// ID of the move being performed
+    #[serde(default)]
+    pub move_id: Option<usize>,
+    /// Frames elapsed since the current move started
+    #[serde(default)]
+    pub move_frame: Option<usize>,
+    /// Total frames of the current move, including its recovery
+    #[serde(default)]
+    pub move_length: Option<usize>,
+    /// Attack flags of the current move
+    #[serde(default)]
+    pub attack_flags: Option<usize>,
+    /// First frame of the current move that can hit
+    #[serde(default)]
+    pub active_start: Option<usize>,
+    /// Last frame of the current move that can hit
+    #[serde(default)]
+    pub active_end: Option<usize>,
+    /// Stance flags of the player
+    #[serde(default)]
+    pub stance_flags: Option<usize>,
+    /// Whether rage is still available
+    #[serde(default)]
+    pub rage: Option<usize>,
+    /// Hits taken in the combo the player is caught in (`OtherComboCount`)
+    #[serde(default)]
+    pub combo_count: Option<usize>,
+    /// Whether the player is throwing the opponent
+    #[serde(default)]
+    pub throwing: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoundOffsets {
+    /// Round count
+    pub count: PointerChain,
+    /// Frames elapsed in the current round
+    pub timer: PointerChain,
+    /// Frames left to break the throw in progress
+    #[serde(default)]
+    pub throw_timer: Option<PointerChain>,
+}
+
+impl OffsetTable {
+    /// Loads an offset table, parsing `.json` files as JSON and anything else as TOML
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json_str(&contents),
+            _ => Self::from_toml_str(&contents),
+        }
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        toml::from_str(contents).map_err(|e| e.into())
+    }
+
+    pub fn from_json_str(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        serde_json::from_str(contents).map_err(|e| e.into())
+    }
+
+    pub fn to_toml_string(&self) -> Result<String, Box<dyn std::error::Error>> {
+        // Going through `toml::Value` writes pointer chain tables after plain offsets
+        let table = toml::Value::try_from(self)?;
+        toml::to_string(&table).map_err(|e| e.into())
+    }
+
+    /// Field paths of the optional offsets the table doesn't include
+    pub fn missing_offsets(&self) -> Vec<&'static str> {
+        let players = &self.players;
+
+        [
+            ("players.move_state", players.move_state.is_none()),
+            ("players.move_id", players.move_id.is_none()),
+            ("players.move_frame", players.move_frame.is_none()),
+            ("players.move_length", players.move_length.is_none()),
+            ("players.attack_flags", players.attack_flags.is_none()),
+            ("players.active_start", players.active_start.is_none()),
+            ("players.active_end", players.active_end.is_none()),
+            ("players.stance_flags", players.stance_flags.is_none()),
+            ("players.rage", players.rage.is_none()),
+            ("players.combo_count", players.combo_count.is_none()),
+            ("players.throwing", players.throwing.is_none()),
+            ("round.throw_timer", self.round.throw_timer.is_none()),
+        ]
+        .iter()
+        .filter(|(_, missing)| *missing)
+        .map(|(name, _)| *name)
+        .collect()
+    }
+
+    /// Overwrites the offset at the field path `name`, e.g. `players.health`,
+    /// setting optional offsets the table doesn't have yet.
+    /// Returns `false` if no such field exists.
+    pub fn set_offset(&mut self, name: &str, value: usize) -> bool {
+        match self.offset_mut(name) {
+            Some(offset) => {
+                *offset = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn offset_mut(&mut self, name: &str) -> Option<&mut usize> {
+        let players = &mut self.players;
+        let round = &mut self.round;
+
+        match name {
+            "players.player_one_base" => Some(&mut players.player_one_base.base),
+            "players.player_two_base" => Some(&mut players.player_two_base.base),
+            "players.player_one_facing" => Some(&mut players.player_one_facing.base),
+            "players.player_two_facing" => Some(&mut players.player_two_facing.base),
+            "players.character_id" => Some(&mut players.character_id),
+            "players.coordinate_x" => Some(&mut players.coordinate_x),
+            "players.coordinate_y" => Some(&mut players.coordinate_y),
+            "players.coordinate_z" => Some(&mut players.coordinate_z),
+            "players.health" => Some(&mut players.health),
+            "players.input_attack" => Some(&mut players.input_attack),
+            "players.input_direction" => Some(&mut players.input_direction),
+            "players.move_state" => Some(players.move_state.get_or_insert(0)),
+            "players.move_id" => Some(players.move_id.get_or_insert(0)),
+            "players.move_frame" => Some(players.move_frame.get_or_insert(0)),
+            "players.move_length" => Some(players.move_length.get_or_insert(0)),
+            "players.attack_flags" => Some(players.attack_flags.get_or_insert(0)),
+            "players.active_start" => Some(players.active_start.get_or_insert(0)),
+            "players.active_end" => Some(players.active_end.get_or_insert(0)),
+            "players.stance_flags" => Some(players.stance_flags.get_or_insert(0)),
+            "players.rage" => Some(players.rage.get_or_insert(0)),
+            "players.combo_count" => Some(players.combo_count.get_or_insert(0)),
+            "players.throwing" => Some(players.throwing.get_or_insert(0)),
+            "round.count" => Some(&mut round.count.base),
+            "round.timer" => Some(&mut round.timer.base),
+            "round.throw_timer" => Some(&mut round.throw_timer.get_or_insert_with(|| PointerChain::fixed(0)).base),
+            _ => None,
+        }
+    }
+}
+
+/// `MemoryModel` whose offsets all come from an `OffsetTable`
+#[derive(Clone)]
+pub struct OffsetModel<B> {
+    backend: B,
+    table: OffsetTable,
+}
+
+impl<B: MemoryBackend> OffsetModel<B> {
+    pub fn new(backend: B, table: OffsetTable) -> Self {
+        Self { backend, table }
+    }
+
+    pub fn table(&self) -> &OffsetTable {
+        &self.table
+    }
+}
+
+impl<B: MemoryBackend> MemoryModel for OffsetModel<B> {
+    type Backend = B;
+
+    fn player_base_address(&self, player: Player) -> &PointerChain {
+        match player {
+            Player::One => &self.table.players.player_one_base,
+            Player::Two => &self.table.players.player_two_base,
+        }
+    }
+
+    fn input_attack_address(&self, _player: Player) -> usize {
+        self.table.players.input_attack
+    }
+
+    fn input_direction_address(&self, _player: Player) -> usize {
+        self.table.players.input_direction
+    }
+
+    fn player_health_address(&self, _player: Player) -> usize {
+        self.table.players.health
+    }
+
+    fn player_character_id_address(&self, _player: Player) -> usize {
+        self.table.players.character_id
+    }
+
+    fn player_coordinate_x(&self, _player: Player) -> usize {
+        self.table.players.coordinate_x
+    }
+
+    fn player_coordinate_y(&self, _player: Player) -> usize {
+        self.table.players.coordinate_y
+    }
+
+    fn player_coordinate_z(&self, _player: Player) -> usize {
+        self.table.players.coordinate_z
+    }
+
+    fn player_facing_address(&self, player: Player) -> &PointerChain {
+        match player {
+            Player::One => &self.table.players.player_one_facing,
+            Player::Two => &self.table.players.player_two_facing,
+        }
+    }
+
+    fn player_move_state_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.move_state
+    }
+
+    fn player_move_id_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.move_id
+    }
+
+    fn player_move_frame_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.move_frame
+    }
+
+    fn player_move_length_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.move_length
+    }
+
+    fn player_attack_flags_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.attack_flags
+    }
+
+    fn player_active_start_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.active_start
+    }
+
+    fn player_active_end_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.active_end
+    }
+
+    fn player_stance_flags_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.stance_flags
+    }
+
+    fn player_rage_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.rage
+    }
+
+    fn player_combo_count_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.combo_count
+    }
+
+    fn player_throwing_address(&self, _player: Player) -> Option<usize> {
+        self.table.players.throwing
+    }
+
+    fn round_count_address(&self) -> &PointerChain {
+        &self.table.round.count
+    }
+
+    fn round_timer_address(&self) -> &PointerChain {
+        &self.table.round.timer
+    }
+
+    fn throw_timer_address(&self) -> Option<&PointerChain> {
+        self.table.round.throw_timer.as_ref()
+    }
+
+    fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    fn game_version(&self) -> &str {
+        &self.table.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::models::season_three;
+
+    #[test]
+    fn lists_the_optional_offsets_a_table_lacks() {
+        let mut table = season_three::v3_dot_33();
+        assert!(table.missing_offsets().contains(&"players.move_state"));
+        assert!(!table.missing_offsets().contains(&"players.throwing"));
+
+        assert!(table.set_offset("players.move_state", 0x200));
+        assert!(!table.missing_offsets().contains(&"players.move_state"));
+    }
+}
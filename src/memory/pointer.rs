@@ -0,0 +1,216 @@
+//! Multi-level pointer chains.
+//!
+//! Values that don't live at a static address are reached by following a
+//! chain of pointers: starting at `base` (relative to the game module), each
+//! hop dereferences a pointer of its width and adds its offset.
+
+use byteorder::{ByteOrder, LittleEndian};
+use serde::{Deserialize, Serialize};
+
+use super::backends::MemoryBackend;
+use super::errors::{MemoryReadDiagnostics, MemoryReadErrors};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PointerWidth {
+    U32,
+    #[default]
+    U64,
+}
+
+/// Single dereference in a pointer chain
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Hop {
+    /// Added to the pointer read by this hop
+    pub offset: usize,
+    #[serde(default)]
+    pub width: PointerWidth,
+}
+
+/// Address reached by following pointers from a module relative `base`.
+///
+/// Offset tables may write a chain without hops as a plain integer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "ChainSpec", into = "ChainSpec")]
+pub struct PointerChain {
+    pub base: usize,
+    pub hops: Vec<Hop>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum ChainSpec {
+    Static(usize),
+    Chain {
+        base: usize,
+        #[serde(default)]
+        hops: Vec<Hop>,
+    },
+}
+
+impl PointerWidth {
+    pub fn size(&self) -> usize {
+        match self {
+            PointerWidth::U32 => 4,
+            PointerWidth::U64 => 8,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> usize {
+        match self {
+            PointerWidth::U32 => LittleEndian::read_u32(bytes) as usize,
+            PointerWidth::U64 => LittleEndian::read_u64(bytes) as usize,
+        }
+    }
+}
+
+impl PointerChain {
+    /// Chain pointing straight at a static address
+    pub fn fixed(base: usize) -> Self {
+        Self {
+            base,
+            hops: Vec::new(),
+        }
+    }
+
+    /// Follows the chain and returns the final address.
+    ///
+    /// Fails with `PointerChainBroken` when a hop can't be read or reads a
+    /// null pointer, reporting the hop index and the address it read from,
+    /// and with `ShortRead` when the backend returns part of a pointer.
+    pub fn resolve<B: MemoryBackend>(&self, backend: &B) -> Result<usize, MemoryReadErrors> {
+        let mut address = backend.module_base() + self.base;
+
+        for (index, hop) in self.hops.iter().enumerate() {
+            let broken = |reason: String| {
+                let diagnostics =
+                    MemoryReadDiagnostics::new(address, "PointerChain::resolve(&self)".into());
+
+                if backend.is_alive() {
+                    MemoryReadErrors::PointerChainBroken(index, reason, diagnostics)
+                } else {
+                    MemoryReadErrors::ProcessGone(diagnostics)
+                }
+            };
+
+            let bytes = backend.copy(address, hop.width.size()).map_err(|e| broken(e.to_string()))?;
+            if bytes.len() < hop.width.size() {
+                return Err(MemoryReadErrors::ShortRead(
+                    hop.width.size(),
+                    bytes.len(),
+                    MemoryReadDiagnostics::new(address, "PointerChain::resolve(&self)".into()),
+                ));
+            }
+
+            let pointer = hop.width.decode(&bytes);
+
+            if pointer == 0 {
+                return Err(broken("null pointer".into()));
+            }
+
+            address = pointer + hop.offset;
+        }
+
+        Ok(address)
+    }
+}
+
+impl From<ChainSpec> for PointerChain {
+    fn from(spec: ChainSpec) -> Self {
+        match spec {
+            ChainSpec::Static(base) => PointerChain::fixed(base),
+            ChainSpec::Chain { base, hops } => PointerChain { base, hops },
+        }
+    }
+}
+
+impl From<PointerChain> for ChainSpec {
+    fn from(chain: PointerChain) -> Self {
+        if chain.hops.is_empty() {
+            ChainSpec::Static(chain.base)
+        } else {
+            ChainSpec::Chain {
+                base: chain.base,
+                hops: chain.hops,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::memory::backends::{MemorySnapshot, SnapshotReplay};
+
+    const MODULE: usize = 0x1_4000_0000;
+
+    /// Chain of a 64-bit hop at 0x100 followed by a 32-bit hop
+    fn chain() -> PointerChain {
+        PointerChain {
+            base: 0x100,
+            hops: vec![
+                Hop {
+                    offset: 0x10,
+                    width: PointerWidth::U64,
+                },
+                Hop {
+                    offset: 0x8,
+                    width: PointerWidth::U32,
+                },
+            ],
+        }
+    }
+
+    fn memory(first: u64, second: Option<u32>) -> SnapshotReplay {
+        let mut snapshot = MemorySnapshot::new();
+        snapshot.insert(MODULE + 0x100, first.to_le_bytes().to_vec());
+        if let Some(second) = second {
+            snapshot.insert(0x5000 + 0x10, second.to_le_bytes().to_vec());
+        }
+        SnapshotReplay::new(vec![snapshot])
+    }
+
+    fn broken_hop(result: Result<usize, MemoryReadErrors>) -> (usize, String, usize) {
+        match result {
+            Err(MemoryReadErrors::PointerChainBroken(hop, reason, diag)) => (hop, reason, diag.memory_address()),
+            other => panic!("expected a broken chain, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn follows_every_hop() {
+        assert_eq!(chain().resolve(&memory(0x5000, Some(0x9000))).unwrap(), 0x9008);
+        assert_eq!(PointerChain::fixed(0x20).resolve(&memory(0, None)).unwrap(), MODULE + 0x20);
+    }
+
+    #[test]
+    fn reports_the_hop_that_broke() {
+        let (hop, reason, address) = broken_hop(chain().resolve(&memory(0x5000, Some(0))));
+        assert_eq!((hop, reason.as_str(), address), (1, "null pointer", 0x5010));
+
+        let (hop, _, address) = broken_hop(chain().resolve(&memory(0x5000, None)));
+        assert_eq!((hop, address), (1, 0x5010));
+
+        let (hop, reason, address) = broken_hop(chain().resolve(&memory(0, None)));
+        assert_eq!((hop, reason.as_str(), address), (0, "null pointer", MODULE + 0x100));
+    }
+
+    /// Backend that only ever returns a single byte
+    struct ShortBackend;
+
+    impl MemoryBackend for ShortBackend {
+        fn copy(&self, _address: usize, _length: usize) -> io::Result<Vec<u8>> {
+            Ok(vec![1])
+        }
+    }
+
+    #[test]
+    fn partial_pointers_are_short_reads() {
+        match chain().resolve(&ShortBackend) {
+            Err(MemoryReadErrors::ShortRead(8, 1, diag)) => assert_eq!(diag.memory_address(), MODULE + 0x100),
+            other => panic!("expected a short read, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Byte-signature (AOB) scanning.
+//!
+//! Patches move the static addresses the offset tables point at, but the
+//! code that uses them rarely changes. Signatures match that code inside
+//! the module image, with `??` wildcards over the bytes that do change,
+//! and derive the offset from the matched instruction.
+
+use std::error::Error;
+use std::str::FromStr;
+
+use byteorder::{ByteOrder, LittleEndian};
+use serde::{Deserialize, Serialize};
+
+use super::backends::MemoryBackend;
+use super::models::OffsetTable;
+
+/// Size of the chunks the module image is read in, so that a single
+/// unreadable page doesn't fail the whole read
+const IMAGE_CHUNK_SIZE: usize = 0x10000;
+
+/// Byte pattern where `None` matches any byte
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    bytes: Vec<Option<u8>>,
+}
+
+/// Named byte signature, resolved into the offset table field `name`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Signature {
+    /// Offset table field the signature resolves, e.g. `round.timer`
+    pub name: String,
+    /// Space separated hex bytes, with `??` for wildcards
+    pub pattern: String,
+    /// Distance from the start of the match to the operand
+    #[serde(default)]
+    pub offset: usize,
+    pub kind: SignatureKind,
+    /// Distance from the start of the match to the end of the instruction,
+    /// which RIP-relative displacements are resolved against
+    #[serde(default)]
+    pub instruction_end: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureKind {
+    /// Address of the operand itself, relative to the module
+    Address,
+    /// 32-bit RIP-relative displacement stored in the operand
+    Relative,
+    /// 32-bit value stored in the operand, such as a struct field offset
+    Value,
+}
+
+#[derive(Debug)]
+pub enum ScanErrors {
+    InvalidPattern(String),
+    NotFound,
+    Ambiguous(usize),
+    OutOfBounds(usize),
+    /// End of the instruction and the displacement that points outside of
+    /// the address space from there
+    TargetOutOfRange(usize, i32),
+    UnknownField(String),
+}
+
+/// Outcome of scanning for every signature in an offset table
+pub struct ScanReport {
+    pub resolved: Vec<(String, usize)>,
+    pub failed: Vec<(String, ScanErrors)>,
+}
+
+/// Searches a copy of the game module image for signatures
+pub struct Scanner {
+    image: Vec<u8>,
+}
+
+impl Pattern {
+    /// Every offset in `haystack` the pattern matches at
+    pub fn find_all(&self, haystack: &[u8]) -> Vec<usize> {
+        if self.bytes.is_empty() || haystack.len() < self.bytes.len() {
+            return Vec::new();
+        }
+
+        (0..=haystack.len() - self.bytes.len())
+            .filter(|&start| {
+                self.bytes
+                    .iter()
+                    .zip(&haystack[start..])
+                    .all(|(p, b)| p.is_none_or(|p| p == *b))
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = ScanErrors;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let bytes = pattern
+            .split_whitespace()
+            .map(|byte| match byte {
+                "?" | "??" => Ok(None),
+                _ => u8::from_str_radix(byte, 16)
+                    .map(Some)
+                    .map_err(|_| ScanErrors::InvalidPattern(pattern.to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if bytes.is_empty() {
+            return Err(ScanErrors::InvalidPattern(pattern.to_string()));
+        }
+
+        Ok(Self { bytes })
+    }
+}
+
+impl Scanner {
+    /// Scans a synthetic or previously dumped module image
+    pub fn new(image: Vec<u8>) -> Self {
+        Self { image }
+    }
+
+    /// Copies `image_size` bytes of the game module out of `backend`.
+    /// Chunks that can't be read are left zeroed.
+    pub fn from_backend<B: MemoryBackend>(backend: &B, image_size: usize) -> Self {
+        let base = backend.module_base();
+        let mut image = vec![0u8; image_size];
+
+        for start in (0..image_size).step_by(IMAGE_CHUNK_SIZE) {
+            let length = IMAGE_CHUNK_SIZE.min(image_size - start);
+            if let Ok(chunk) = backend.copy(base + start, length) {
+                image[start..start + length].copy_from_slice(&chunk);
+            }
+        }
+
+        Self { image }
+    }
+
+    pub fn image(&self) -> &[u8] {
+        &self.image
+    }
+
+    /// Finds the single match of `signature` and derives its module relative offset
+    pub fn resolve(&self, signature: &Signature) -> Result<usize, ScanErrors> {
+        let pattern = signature.pattern.parse::<Pattern>()?;
+
+        let matches = pattern.find_all(&self.image);
+        let start = match matches.len() {
+            0 => return Err(ScanErrors::NotFound),
+            1 => matches[0],
+            n => return Err(ScanErrors::Ambiguous(n)),
+        };
+
+        let operand = start + signature.offset;
+        match signature.kind {
+            SignatureKind::Address => Ok(operand),
+            SignatureKind::Relative => {
+                let displacement = self.read_u32(operand)? as i32;
+                let instruction_end = start + signature.instruction_end;
+                instruction_end
+                    .checked_add_signed(displacement as isize)
+                    .ok_or(ScanErrors::TargetOutOfRange(instruction_end, displacement))
+            }
+            SignatureKind::Value => self.read_u32(operand).map(|v| v as usize),
+        }
+    }
+
+    /// Resolves every signature in `table`, relocating each field that resolved
+    pub fn relocate(&self, table: &mut OffsetTable) -> ScanReport {
+        let mut report = ScanReport {
+            resolved: Vec::new(),
+            failed: Vec::new(),
+        };
+
+        for signature in table.signatures.clone() {
+            let resolved = self.resolve(&signature).and_then(|offset| {
+                if table.set_offset(&signature.name, offset) {
+                    Ok(offset)
+                } else {
+                    Err(ScanErrors::UnknownField(signature.name.clone()))
+                }
+            });
+
+            match resolved {
+                Ok(offset) => report.resolved.push((signature.name, offset)),
+                Err(e) => report.failed.push((signature.name, e)),
+            }
+        }
+
+        report
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32, ScanErrors> {
+        self.image
+            .get(offset..offset + 4)
+            .map(LittleEndian::read_u32)
+            .ok_or(ScanErrors::OutOfBounds(offset))
+    }
+}
+
+impl std::fmt::Display for ScanErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanErrors::InvalidPattern(pattern) => write!(f, "invalid pattern `{}`", pattern),
+            ScanErrors::NotFound => write!(f, "signature not found"),
+            ScanErrors::Ambiguous(count) => write!(f, "signature matched {} times", count),
+            ScanErrors::OutOfBounds(offset) => {
+                write!(f, "operand at 0x{:X} is outside of the module image", offset)
+            }
+            ScanErrors::TargetOutOfRange(instruction_end, displacement) => write!(
+                f,
+                "displacement {} from 0x{:X} points outside of the address space",
+                displacement, instruction_end
+            ),
+            ScanErrors::UnknownField(name) => write!(f, "no offset table field named `{}`", name),
+        }
+    }
+}
+
+impl Error for ScanErrors {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::models::season_three;
+
+    /// Module image holding a RIP-relative load of 0x300 at 0x100 and a
+    /// load of the struct field 0x1234 at 0x200
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; 0x400];
+        // mov rax, [rip + 0x1F9]
+        image[0x100..0x109].copy_from_slice(&[0x48, 0x8B, 0x05, 0xF9, 0x01, 0x00, 0x00, 0x89, 0xC1]);
+        // mov eax, [rcx + 0x1234]
+        image[0x200..0x207].copy_from_slice(&[0x8B, 0x81, 0x34, 0x12, 0x00, 0x00, 0xC3]);
+        image
+    }
+
+    fn signature(name: &str, pattern: &str, offset: usize, kind: SignatureKind, instruction_end: usize) -> Signature {
+        Signature {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            offset,
+            kind,
+            instruction_end,
+        }
+    }
+
+    #[test]
+    fn patterns_match_wildcards() {
+        let pattern: Pattern = "8B 81 ?? ?? 00 00".parse().unwrap();
+
+        assert_eq!(pattern.len(), 6);
+        assert!(!pattern.is_empty());
+        assert_eq!(pattern.find_all(&image()), vec![0x200]);
+        assert!("8B 8G".parse::<Pattern>().is_err());
+        assert!("".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn resolves_signatures_in_a_synthetic_image() {
+        let scanner = Scanner::new(image());
+
+        let relative = signature("round.timer", "48 8B 05 ?? ?? ?? ?? 89", 3, SignatureKind::Relative, 7);
+        let value = signature("players.move_id", "8B 81 ?? ?? ?? ?? C3", 2, SignatureKind::Value, 0);
+        let address = signature("round.count", "8B 81", 0, SignatureKind::Address, 0);
+
+        assert_eq!(scanner.resolve(&relative).unwrap(), 0x300);
+        assert_eq!(scanner.resolve(&value).unwrap(), 0x1234);
+        assert_eq!(scanner.resolve(&address).unwrap(), 0x200);
+    }
+
+    #[test]
+    fn relative_targets_before_the_image_fail() {
+        let mut image = image();
+        // mov rax, [rip - 0x200], ending at 0x107
+        image[0x103..0x107].copy_from_slice(&(-0x200i32).to_le_bytes());
+        let scanner = Scanner::new(image);
+
+        let relative = signature("round.timer", "48 8B 05 ?? ?? ?? ?? 89", 3, SignatureKind::Relative, 7);
+        match scanner.resolve(&relative) {
+            Err(ScanErrors::TargetOutOfRange(0x107, -0x200)) => (),
+            other => panic!("expected an out of range target, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn relocates_offsets_and_reports_failures() {
+        let scanner = Scanner::new(image());
+        let mut table = season_three::v3_dot_33();
+        assert!(table.players.move_id.is_none());
+
+        table.signatures = vec![
+            signature("round.timer", "48 8B 05 ?? ?? ?? ?? 89", 3, SignatureKind::Relative, 7),
+            signature("players.move_id", "8B 81 ?? ?? ?? ?? C3", 2, SignatureKind::Value, 0),
+            signature("round.count", "DE AD BE EF", 0, SignatureKind::Address, 0),
+            signature("round.throw_timer", "00 00 00 00", 0, SignatureKind::Address, 0),
+            signature("players.unknown", "8B 81", 0, SignatureKind::Address, 0),
+        ];
+
+        let report = scanner.relocate(&mut table);
+
+        assert_eq!(
+            report.resolved,
+            vec![("round.timer".to_string(), 0x300), ("players.move_id".to_string(), 0x1234)]
+        );
+        assert_eq!(table.round.timer.base, 0x300);
+        assert_eq!(table.players.move_id, Some(0x1234));
+
+        let failed: Vec<_> = report.failed.iter().map(|(name, e)| (name.as_str(), e)).collect();
+        match failed.as_slice() {
+            [("round.count", ScanErrors::NotFound), ("round.throw_timer", ScanErrors::Ambiguous(_)), ("players.unknown", ScanErrors::UnknownField(_))] => (),
+            _ => panic!("unexpected failures: {:?}", report.failed),
+        }
+    }
+}
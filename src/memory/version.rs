@@ -0,0 +1,215 @@
+//! Game version detection.
+//!
+//! Every build of the game executable carries the link timestamp in its PE
+//! header, which is read out of the loaded module and matched against the
+//! `[build]` section of each known offset table. Builds no table matches are
+//! read with the bundled offsets, along with a warning.
+
+use std::error::Error;
+use std::path::Path;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::backends::MemoryBackend;
+use super::errors::{MemoryReadDiagnostics, MemoryReadErrors};
+use super::models::{season_three, OffsetTable};
+
+/// Offset of `e_lfanew` in the DOS header
+const DOS_PE_OFFSET: usize = 0x3C;
+/// Offset of `TimeDateStamp` from the PE signature
+const PE_TIMESTAMP_OFFSET: usize = 0x8;
+/// Offset of `SizeOfImage` from the PE signature
+const PE_IMAGE_SIZE_OFFSET: usize = 0x50;
+
+/// Identifies the build of the running game executable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildId {
+    /// Address the game module was read from
+    pub module_base: usize,
+    /// Link timestamp from the PE header
+    pub timestamp: u32,
+    /// Size of the loaded module image
+    pub image_size: u32,
+}
+
+/// Offset tables for every supported game version
+pub struct VersionRegistry {
+    tables: Vec<OffsetTable>,
+    /// Index of the table used for builds no table matches
+    fallback: Option<usize>,
+}
+
+impl BuildId {
+    /// Reads the build of the game module out of its PE header
+    pub fn detect<B: MemoryBackend>(backend: &B) -> Result<Self, Box<dyn Error>> {
+        let base = backend.module_base();
+
+        let not_pe = |address: usize| {
+            Box::new(MemoryReadErrors::UnsupportedVersion(
+                "the game module is not a PE image".into(),
+                MemoryReadDiagnostics::new(address, "BuildId::detect(&backend)".into()),
+            ))
+        };
+
+        let dos_header = backend.copy(base, DOS_PE_OFFSET + 4)?;
+        if &dos_header[..2] != b"MZ" {
+            return Err(not_pe(base));
+        }
+
+        let pe_address = base + LittleEndian::read_u32(&dos_header[DOS_PE_OFFSET..]) as usize;
+        let pe_header = backend.copy(pe_address, PE_IMAGE_SIZE_OFFSET + 4)?;
+        if &pe_header[..4] != b"PE\0\0" {
+            return Err(not_pe(pe_address));
+        }
+
+        Ok(Self {
+            module_base: base,
+            timestamp: LittleEndian::read_u32(&pe_header[PE_TIMESTAMP_OFFSET..]),
+            image_size: LittleEndian::read_u32(&pe_header[PE_IMAGE_SIZE_OFFSET..]),
+        })
+    }
+}
+
+impl VersionRegistry {
+    /// Registry holding only the offset tables bundled into the executable,
+    /// falling back to the 3.33 offsets for unknown builds
+    pub fn bundled() -> Self {
+        Self {
+            tables: vec![season_three::v3_dot_33()],
+            fallback: Some(0),
+        }
+    }
+
+    pub fn register(&mut self, table: OffsetTable) {
+        self.tables.push(table);
+    }
+
+    /// Registers every `.toml` and `.json` offset table in `dir`
+    pub fn load_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), Box<dyn Error>> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("toml") | Some("json") => self.register(OffsetTable::load(&path)?),
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn tables(&self) -> &[OffsetTable] {
+        &self.tables
+    }
+
+    /// Finds the offset table built for `build`, or the fallback table when
+    /// none of them is
+    pub fn select(&self, build: BuildId) -> Result<&OffsetTable, MemoryReadErrors> {
+        let exact = self
+            .tables
+            .iter()
+            .find(|table| table.build.is_some_and(|b| b.timestamp == build.timestamp));
+        if let Some(table) = exact {
+            return Ok(table);
+        }
+
+        if let Some(table) = self.fallback.and_then(|index| self.tables.get(index)) {
+            println!(
+                "Unknown game build (timestamp 0x{:08X}), falling back to the offsets of version {}. \
+                 Pass --offsets if the captured values look wrong",
+                build.timestamp, table.version
+            );
+            return Ok(table);
+        }
+
+        let versions: Vec<_> = self.tables.iter().map(|t| t.version.as_str()).collect();
+        Err(MemoryReadErrors::UnsupportedVersion(
+            format!(
+                "unknown build (timestamp 0x{:08X}, image size 0x{:X}). \
+                 Known versions: {}. Add `[build] timestamp = 0x{:08X}` to a \
+                 matching offset table or pass one with --offsets",
+                build.timestamp,
+                build.image_size,
+                versions.join(", "),
+                build.timestamp
+            ),
+            MemoryReadDiagnostics::new(build.module_base, "VersionRegistry::select(&self)".into()),
+        ))
+    }
+
+    /// Detects the running build and selects its offset table
+    pub fn detect<B: MemoryBackend>(&self, backend: &B) -> Result<&OffsetTable, Box<dyn Error>> {
+        let build = BuildId::detect(backend)?;
+        self.select(build).map_err(|e| e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::backends::{MemorySnapshot, SnapshotReplay};
+    use crate::memory::models::offsets::BuildSignature;
+
+    const MODULE: usize = 0x1_4000_0000;
+    const PE_ADDRESS: usize = 0x80;
+
+    fn build(timestamp: u32) -> BuildId {
+        BuildId {
+            module_base: MODULE,
+            timestamp,
+            image_size: 0x400_0000,
+        }
+    }
+
+    fn table(version: &str, timestamp: Option<u32>) -> OffsetTable {
+        let mut table = season_three::v3_dot_33();
+        table.version = version.to_string();
+        table.build = timestamp.map(|timestamp| BuildSignature { timestamp });
+        table
+    }
+
+    /// Module starting with a DOS header pointing at a PE header
+    fn module(signature: &[u8; 4]) -> SnapshotReplay {
+        let mut image = vec![0u8; PE_ADDRESS + PE_IMAGE_SIZE_OFFSET + 4];
+        image[..2].copy_from_slice(b"MZ");
+        LittleEndian::write_u32(&mut image[DOS_PE_OFFSET..], PE_ADDRESS as u32);
+        image[PE_ADDRESS..PE_ADDRESS + 4].copy_from_slice(signature);
+        LittleEndian::write_u32(&mut image[PE_ADDRESS + PE_TIMESTAMP_OFFSET..], 0x5F3A_1B2C);
+        LittleEndian::write_u32(&mut image[PE_ADDRESS + PE_IMAGE_SIZE_OFFSET..], 0x400_0000);
+
+        let mut snapshot = MemorySnapshot::new();
+        snapshot.insert(MODULE, image);
+        SnapshotReplay::new(vec![snapshot])
+    }
+
+    #[test]
+    fn detects_builds_from_the_pe_header() {
+        assert_eq!(BuildId::detect(&module(b"PE\0\0")).unwrap(), build(0x5F3A_1B2C));
+
+        let error = BuildId::detect(&module(b"NE\0\0")).unwrap_err();
+        assert!(MemoryReadErrors::is_unsupported_version(error.as_ref()));
+    }
+
+    #[test]
+    fn selects_the_table_of_the_build() {
+        let registry = VersionRegistry {
+            tables: vec![table("old", Some(1)), table("new", Some(2)), table("undated", None)],
+            fallback: None,
+        };
+
+        assert_eq!(registry.select(build(2)).unwrap().version, "new");
+        match registry.select(build(3)) {
+            Err(MemoryReadErrors::UnsupportedVersion(reason, _)) => assert!(reason.contains("0x00000003")),
+            other => panic!("expected an unsupported version, got {:?}", other.map(|t| &t.version)),
+        }
+    }
+
+    #[test]
+    fn falls_back_for_unknown_builds() {
+        let mut registry = VersionRegistry::bundled();
+        registry.register(table("dated", Some(1)));
+
+        assert_eq!(registry.select(build(1)).unwrap().version, "dated");
+        assert_eq!(registry.select(build(3)).unwrap().version, "3.33");
+        assert_eq!(registry.detect(&module(b"PE\0\0")).unwrap().version, "3.33");
+    }
+}
@@ -0,0 +1,115 @@
+//! Loading captured matches back in for analysis.
+//!
+//! ```ignore
+//! let library = Library::load_dir("Tekken7Replays")?;
+//!
+//! for frame in library.query().character(Character::Law).round(1).frames(0..600).players() {
+//!     println!("{} {}", frame.round_frame, frame.state.input_direction());
+//! }
+//! ```
+
+pub mod playback;
+pub mod query;
+pub mod transcript;
+
+pub use playback::Playback;
+pub use query::{PlayerFrame, Query};
+pub use transcript::{Press, Transcript};
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::capture;
+use crate::globals::{InputButton, InputDirection, Player};
+use crate::states::game_state::RoundState;
+use crate::states::match_state::{MatchHeader, MatchState, RoundRecord};
+
+/// Single captured match along with the file it was loaded from
+pub struct Replay {
+    path: PathBuf,
+    match_state: MatchState,
+}
+
+/// Every replay loaded from a directory
+pub struct Library {
+    replays: Vec<Replay>,
+}
+
+impl Replay {
+    /// Loads a capture, a JSON match document or a JSON round from older builds
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            match_state: capture::load_match(&path)?,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn header(&self) -> &MatchHeader {
+        self.match_state.header()
+    }
+
+    pub fn rounds(&self) -> &[RoundRecord] {
+        self.match_state.rounds()
+    }
+
+    /// Every frame of the match, in order
+    pub fn frames(&self) -> impl Iterator<Item = &RoundState> {
+        self.rounds().iter().flat_map(|round| round.states.iter())
+    }
+
+    pub fn query(&self) -> Query<'_> {
+        Query::new(vec![self])
+    }
+}
+
+impl Library {
+    pub fn new(replays: Vec<Replay>) -> Self {
+        Self { replays }
+    }
+
+    /// Loads every capture and JSON file in `dir`. Partial captures that
+    /// are still being written are skipped.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Self, Box<dyn Error>> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            match path.extension().and_then(|e| e.to_str()) {
+                Some(capture::EXTENSION) | Some("json") => paths.push(path),
+                _ => (),
+            }
+        }
+        paths.sort();
+
+        let replays = paths
+            .into_iter()
+            .map(|path| Replay::load(&path).map_err(|e| format!("{}: {}", path.display(), e).into()))
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+        Ok(Self::new(replays))
+    }
+
+    pub fn replays(&self) -> &[Replay] {
+        &self.replays
+    }
+
+    pub fn query(&self) -> Query<'_> {
+        Query::new(self.replays.iter().collect())
+    }
+}
+
+/// Direction and buttons recorded for `player` on `state`
+pub(crate) fn recorded_inputs(state: &RoundState, player: Player) -> Result<(InputDirection, InputButton), Box<dyn Error>> {
+    let player_state = state.get_player_state(player);
+
+    let direction = InputDirection::try_from(player_state.input_direction() as usize)
+        .map_err(|value| format!("Unknown direction value {} on frame {}", value, state.get_round_frame()))?;
+    let button = InputButton::try_from(player_state.input_attack() as usize)
+        .map_err(|value| format!("Unknown button value {} on frame {}", value, state.get_round_frame()))?;
+
+    Ok((direction, button))
+}
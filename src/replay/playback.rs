@@ -0,0 +1,195 @@
+//! Playing captured inputs back into the game.
+//!
+//! Recorded directions are relative to where the player faces, so each
+//! frame is pressed with the keys for the side the player was facing from
+//! on that frame. Held directions are switched over to the other side's keys
+//! as soon as the recorded facing flips.
+
+use std::error::Error;
+
+use super::recorded_inputs;
+use crate::globals::{Facing, Player};
+use crate::input::scheduler::{FrameClock, Inputs, Schedule, ScheduleReport, Scheduler};
+use crate::input::{InputSink, KeyBindings};
+use crate::states::game_state::RoundState;
+use crate::states::match_state::RoundRecord;
+
+/// Presses the recorded inputs of one player in a captured round
+pub struct Playback<'a> {
+    states: &'a [RoundState],
+    /// Player whose inputs are played back
+    source: Player,
+    /// Player whose keys are pressed
+    target: Player,
+    bindings: KeyBindings,
+}
+
+impl<'a> Playback<'a> {
+    /// Plays back `source` using the keys of the same player
+    pub fn new(round: &'a RoundRecord, source: Player) -> Self {
+        Self {
+            states: &round.states,
+            source,
+            target: source,
+            bindings: KeyBindings::default(),
+        }
+    }
+
+    /// Presses the keys of `target` instead, e.g. to play player two's inputs
+    /// against the training mode dummy as player one
+    pub fn controlling(mut self, target: Player) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Presses keys from `bindings` instead of the default layout
+    pub fn with_bindings(mut self, bindings: KeyBindings) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    /// Recorded inputs, timed from the first captured frame. Each frame's
+    /// inputs are held until the next captured frame.
+    pub fn schedule(&self) -> Result<Schedule, Box<dyn Error>> {
+        let mut schedule = Schedule::new();
+        let first_frame = match self.states.first() {
+            Some(state) => state.get_round_frame(),
+            None => return Ok(schedule),
+        };
+
+        for (index, state) in self.states.iter().enumerate() {
+            // Frames captured out of order are pressed right away and held
+            // for a single frame rather than underflowing
+            let frame = state.get_round_frame().saturating_sub(first_frame);
+            let hold = self
+                .states
+                .get(index + 1)
+                .map_or(1, |next| next.get_round_frame().saturating_sub(state.get_round_frame()).max(1));
+
+            schedule.hold(frame, self.inputs(state)?, hold);
+        }
+
+        Ok(schedule)
+    }
+
+    /// Presses every recorded frame into `sink` as `clock` reaches it,
+    /// releasing all keys once done
+    pub fn play<S: InputSink, C: FrameClock>(&self, sink: &mut S, clock: C) -> Result<ScheduleReport, Box<dyn Error>> {
+        let schedule = self.schedule()?;
+        Scheduler::new(clock, self.bindings.player(self.target)).run(&schedule, sink)
+    }
+
+    /// Inputs recorded for the source player on `state`
+    fn inputs(&self, state: &RoundState) -> Result<Inputs, Box<dyn Error>> {
+        let (direction, button) = recorded_inputs(state, self.source)?;
+
+        // Forward points right for a player facing right, the way it does
+        // for player one on the left side
+        let side = match state.get_player_state(self.source).facing() {
+            Facing::Right => Player::One,
+            Facing::Left => Player::Two,
+        };
+
+        Ok(Inputs {
+            button,
+            direction,
+            side,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::globals::{Character, InputButton, InputDirection};
+    use crate::input::recording::RecordingSink;
+    use crate::input::Key;
+    use crate::states::match_state::MatchState;
+    use crate::states::player_state::{PlayerInfo, PlayerState};
+
+    /// Clock moving one frame ahead on every read
+    struct CountingClock(Cell<u128>);
+
+    impl FrameClock for CountingClock {
+        fn frame(&self) -> Result<u128, Box<dyn Error>> {
+            let frame = self.0.get();
+            self.0.set(frame + 1);
+            Ok(frame)
+        }
+    }
+
+    /// Round where player one holds each direction with the given facing
+    fn round(frames: &[(u128, InputDirection, Facing)]) -> RoundRecord {
+        let states: Vec<RoundState> = frames
+            .iter()
+            .map(|(frame, direction, facing)| {
+                let info = || PlayerInfo { screen_name: None };
+                let mut one = PlayerState::blank(Player::One, Character::Law);
+                one.input_direction = *direction as u16;
+                one.input_attack = InputButton::None as u16;
+                one.facing = match facing {
+                    Facing::Left => 0,
+                    Facing::Right => 1,
+                };
+
+                RoundState::new(1, *frame, (info(), info()), (one, PlayerState::blank(Player::Two, Character::Paul)))
+            })
+            .collect();
+
+        let mut match_state = MatchState::new(&states[0], None);
+        match_state.push_round(states, false);
+        match_state.rounds()[0].clone()
+    }
+
+    /// Keys held for each step of the schedule
+    fn keys(playback: &Playback) -> Vec<(u128, Vec<Key>, u128)> {
+        let bindings = KeyBindings::default();
+        let bindings = bindings.player(Player::One);
+
+        playback
+            .schedule()
+            .unwrap()
+            .steps()
+            .iter()
+            .map(|step| (step.frame, step.inputs.direction.to_input_keys(bindings, step.inputs.side), step.hold))
+            .collect()
+    }
+
+    #[test]
+    fn forward_and_back_follow_the_facing() {
+        let round = round(&[
+            (40, InputDirection::Forward, Facing::Right),
+            (41, InputDirection::Back, Facing::Right),
+            (42, InputDirection::Forward, Facing::Left),
+            (43, InputDirection::Back, Facing::Left),
+        ]);
+        let bindings = KeyBindings::default();
+        let (left, right) = (vec![bindings.player_one.left], vec![bindings.player_one.right]);
+
+        assert_eq!(
+            keys(&Playback::new(&round, Player::One)),
+            vec![(0, right.clone(), 1), (1, left.clone(), 1), (2, left, 1), (3, right, 1)]
+        );
+
+        let mut sink = RecordingSink::new();
+        Playback::new(&round, Player::One)
+            .play(&mut sink, CountingClock(Cell::new(0)))
+            .unwrap();
+        assert!(sink.held().is_empty());
+    }
+
+    #[test]
+    fn frames_out_of_order_do_not_underflow() {
+        let round = round(&[
+            (40, InputDirection::Forward, Facing::Right),
+            (38, InputDirection::Down, Facing::Right),
+            (42, InputDirection::Back, Facing::Right),
+        ]);
+        let playback = Playback::new(&round, Player::One);
+
+        let frames: Vec<_> = keys(&playback).iter().map(|(frame, _, hold)| (*frame, *hold)).collect();
+        assert_eq!(frames, vec![(0, 1), (0, 4), (2, 1)]);
+    }
+}
@@ -0,0 +1,228 @@
+use std::ops::Range;
+
+use super::Replay;
+use crate::globals::{Character, Player};
+use crate::states::game_state::RoundState;
+use crate::states::match_state::RoundRecord;
+use crate::states::player_state::PlayerState;
+
+/// State of one player on a single frame
+#[derive(Clone, Copy)]
+pub struct PlayerFrame<'a> {
+    pub replay: &'a Replay,
+    pub round: u8,
+    pub round_frame: u128,
+    pub state: &'a PlayerState,
+}
+
+/// Filters over the rounds of one or more replays. Every filter narrows
+/// down the rounds, frames and players the iterators yield.
+#[derive(Clone)]
+pub struct Query<'a> {
+    replays: Vec<&'a Replay>,
+    character: Option<Character>,
+    player: Option<Player>,
+    round: Option<u8>,
+    frames: Option<Range<u128>>,
+}
+
+impl<'a> Query<'a> {
+    pub fn new(replays: Vec<&'a Replay>) -> Self {
+        Self {
+            replays,
+            character: None,
+            player: None,
+            round: None,
+            frames: None,
+        }
+    }
+
+    /// Keeps rounds where either player uses `character`, and only that
+    /// character's side when iterating over players
+    pub fn character(mut self, character: Character) -> Self {
+        self.character = Some(character);
+        self
+    }
+
+    /// Keeps only one side when iterating over players
+    pub fn player(mut self, player: Player) -> Self {
+        self.player = Some(player);
+        self
+    }
+
+    /// Keeps rounds with the given round number
+    pub fn round(mut self, round: u8) -> Self {
+        self.round = Some(round);
+        self
+    }
+
+    /// Keeps frames whose round frame falls within `frames`
+    pub fn frames(mut self, frames: Range<u128>) -> Self {
+        self.frames = Some(frames);
+        self
+    }
+
+    /// Rounds passing the filters, along with the replay they come from
+    pub fn rounds(&self) -> impl Iterator<Item = (&'a Replay, &'a RoundRecord)> + '_ {
+        self.replays
+            .iter()
+            .flat_map(|replay| replay.rounds().iter().map(move |round| (*replay, round)))
+            .filter(move |(_, round)| self.round.is_none_or(|r| round.result.round == r))
+            .filter(move |(_, round)| {
+                self.character.is_none_or(|character| {
+                    round.states.first().is_some_and(|state| {
+                        self.sides().any(|p| state.get_player_state(p).character() == character)
+                    })
+                })
+            })
+    }
+
+    /// Frames of the matching rounds that fall within the frame range
+    pub fn states(&self) -> impl Iterator<Item = (&'a Replay, &'a RoundState)> + '_ {
+        self.rounds()
+            .flat_map(|(replay, round)| round.states.iter().map(move |state| (replay, state)))
+            .filter(move |(_, state)| {
+                self.frames
+                    .as_ref()
+                    .is_none_or(|frames| frames.contains(&state.get_round_frame()))
+            })
+    }
+
+    /// States of every player passing the filters, frame by frame
+    pub fn players(&self) -> impl Iterator<Item = PlayerFrame<'a>> + '_ {
+        self.states().flat_map(move |(replay, state)| {
+            self.sides()
+                .filter(move |player| {
+                    self.character.is_none_or(|character| {
+                        state.get_player_state(*player).character() == character
+                    })
+                })
+                .map(move |player| PlayerFrame {
+                    replay,
+                    round: state.get_round(),
+                    round_frame: state.get_round_frame(),
+                    state: state.get_player_state(player),
+                })
+        })
+    }
+
+    /// Sides the player filter allows
+    fn sides(&self) -> impl Iterator<Item = Player> {
+        let player = self.player;
+        [Player::One, Player::Two]
+            .iter()
+            .copied()
+            .filter(move |p| player.is_none_or(|player| *p == player))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::states::match_state::MatchState;
+    use crate::states::player_state::PlayerInfo;
+
+    fn round(round: u8, frames: u128, characters: (Character, Character)) -> Vec<RoundState> {
+        let info = || PlayerInfo { screen_name: None };
+
+        (0..frames)
+            .map(|frame| {
+                let players = (
+                    PlayerState::blank(Player::One, characters.0),
+                    PlayerState::blank(Player::Two, characters.1),
+                );
+                RoundState::new(round, frame, (info(), info()), players)
+            })
+            .collect()
+    }
+
+    fn replay(name: &str, rounds: Vec<Vec<RoundState>>) -> Replay {
+        let mut match_state = MatchState::new(&rounds[0][0], None);
+        for states in rounds {
+            match_state.push_round(states, true);
+        }
+
+        Replay {
+            path: name.into(),
+            match_state,
+        }
+    }
+
+    /// Law against Paul over two rounds, and King against Law over one
+    fn replays() -> (Replay, Replay) {
+        (
+            replay("law-paul", vec![round(1, 4, (Character::Law, Character::Paul)), round(2, 3, (Character::Law, Character::Paul))]),
+            replay("king-law", vec![round(1, 2, (Character::King, Character::Law))]),
+        )
+    }
+
+    fn names<'a>(rounds: impl Iterator<Item = (&'a Replay, &'a RoundRecord)>) -> Vec<(String, u8)> {
+        rounds
+            .map(|(replay, round)| (replay.path().display().to_string(), round.result.round))
+            .collect()
+    }
+
+    #[test]
+    fn unfiltered_queries_yield_everything() {
+        let (first, second) = replays();
+        let query = Query::new(vec![&first, &second]);
+
+        assert_eq!(
+            names(query.rounds()),
+            vec![("law-paul".to_string(), 1), ("law-paul".to_string(), 2), ("king-law".to_string(), 1)]
+        );
+        assert_eq!(query.states().count(), 9);
+        assert_eq!(query.players().count(), 18);
+    }
+
+    #[test]
+    fn rounds_are_filtered_by_number_and_character() {
+        let (first, second) = replays();
+        let query = Query::new(vec![&first, &second]);
+
+        assert_eq!(names(query.clone().round(1).rounds()), vec![("law-paul".to_string(), 1), ("king-law".to_string(), 1)]);
+        assert_eq!(names(query.clone().character(Character::King).rounds()), vec![("king-law".to_string(), 1)]);
+        assert!(query.clone().character(Character::Lili).rounds().next().is_none());
+
+        // The character has to be on an allowed side
+        assert_eq!(names(query.character(Character::Law).player(Player::Two).rounds()), vec![("king-law".to_string(), 1)]);
+    }
+
+    #[test]
+    fn frames_are_filtered_by_round_frame() {
+        let (first, second) = replays();
+        let query = Query::new(vec![&first, &second]).frames(1..3);
+
+        let frames: Vec<_> = query.states().map(|(_, state)| (state.get_round(), state.get_round_frame())).collect();
+        assert_eq!(frames, vec![(1, 1), (1, 2), (2, 1), (2, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn players_follow_the_character_and_side() {
+        let (first, second) = replays();
+        let query = Query::new(vec![&first, &second]);
+
+        // Law is player one in the first replay and player two in the second
+        let law: Vec<_> = query
+            .clone()
+            .character(Character::Law)
+            .round(1)
+            .players()
+            .map(|frame| (frame.replay.path().display().to_string(), frame.round_frame, frame.state.player()))
+            .collect();
+        assert_eq!(
+            law,
+            vec![
+                ("law-paul".to_string(), 0, Player::One),
+                ("law-paul".to_string(), 1, Player::One),
+                ("law-paul".to_string(), 2, Player::One),
+                ("law-paul".to_string(), 3, Player::One),
+                ("king-law".to_string(), 0, Player::Two),
+                ("king-law".to_string(), 1, Player::Two),
+            ]
+        );
+
+        let twos: Vec<_> = query.player(Player::Two).players().map(|frame| frame.state.character()).collect();
+        assert_eq!(twos, [vec![Character::Paul; 7], vec![Character::Law; 2]].concat());
+    }
+}
@@ -0,0 +1,284 @@
+//! Reading captured inputs back as notation.
+//!
+//! Captures store the raw direction and buttons of every frame. A
+//! [`Transcript`] collapses them into the presses a player made, written in
+//! the notation [`crate::input::notation`] parses, e.g. `b, f+2, d/f+1`.
+//! Recorded directions are already relative to where the player faces, so
+//! presses read the same from either side of the screen.
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use super::recorded_inputs;
+use crate::globals::{InputButton, InputDirection, Player};
+use crate::states::game_state::RoundState;
+
+/// Frames an input has to be held for to be written as held
+pub const HOLD_FRAMES: u128 = 10;
+/// Inputs pressed within this many frames of each other are written as a
+/// single press, e.g. `f+2` rather than `f, f+2`
+pub const MERGE_FRAMES: u128 = 2;
+
+/// Single press made by a player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Press {
+    /// Round frame the press starts on
+    pub frame: u128,
+    /// Direction held during the press
+    pub direction: InputDirection,
+    /// Buttons newly pressed, without the ones held from earlier presses
+    pub button: InputButton,
+    /// Frames the direction was held for from the start of the press
+    pub direction_frames: u128,
+    /// Frames the buttons were held for
+    pub button_frames: u128,
+}
+
+/// Presses one player made over a round
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    player: Player,
+    presses: Vec<Press>,
+}
+
+impl Press {
+    /// Notation of this press, with held directions in uppercase and held
+    /// buttons followed by `*`
+    pub fn notation(&self) -> String {
+        let mut notation = match self.direction {
+            InputDirection::Neutral => String::new(),
+            direction if self.direction_frames >= HOLD_FRAMES => direction.to_str().to_uppercase(),
+            direction => direction.to_str().to_string(),
+        };
+
+        if self.button != InputButton::None {
+            if !notation.is_empty() {
+                notation.push('+');
+            }
+            notation.push_str(self.button.to_str());
+
+            if self.button_frames >= HOLD_FRAMES {
+                notation.push('*');
+            }
+        }
+
+        notation
+    }
+}
+
+impl Transcript {
+    /// Transcribes the inputs of `player` over consecutive frames of a round
+    pub fn new(states: &[RoundState], player: Player) -> Result<Self, Box<dyn Error>> {
+        let mut presses: Vec<Press> = Vec::new();
+        let mut previous = (InputDirection::Neutral, InputButton::None);
+        // Presses whose direction and buttons are still held
+        let mut open_direction = None;
+        let mut open_button = None;
+
+        for (index, state) in states.iter().enumerate() {
+            let (direction, button) = recorded_inputs(state, player)?;
+            let frame = state.get_round_frame();
+            let frames = states
+                .get(index + 1)
+                .map_or(1, |next| next.get_round_frame().saturating_sub(frame));
+
+            let held = button as usize;
+            let pressed = held & !(previous.1 as usize);
+            let new_direction = direction != previous.0 && direction != InputDirection::Neutral;
+
+            if pressed != 0 || new_direction {
+                let count = presses.len();
+                let merge = presses.last_mut().filter(|last| {
+                    let buttons = last.button as usize;
+                    !new_direction
+                        && last.direction == direction
+                        && held & buttons == buttons
+                        && frame.saturating_sub(last.frame) <= MERGE_FRAMES
+                });
+
+                match merge {
+                    Some(last) => {
+                        last.button = InputButton::try_from(last.button as usize | pressed)
+                            .map_err(|value| format!("Unknown button value {} on frame {}", value, frame))?;
+                        last.button_frames = 0;
+                        open_button = Some(count - 1);
+                    }
+                    None => {
+                        presses.push(Press {
+                            frame,
+                            direction,
+                            button: InputButton::try_from(pressed)
+                                .map_err(|value| format!("Unknown button value {} on frame {}", value, frame))?,
+                            direction_frames: 0,
+                            button_frames: 0,
+                        });
+
+                        let index = presses.len() - 1;
+                        if direction != InputDirection::Neutral {
+                            open_direction = Some(index);
+                        }
+                        open_button = if pressed != 0 { Some(index) } else { None };
+                    }
+                }
+            }
+
+            if let Some(index) = open_direction {
+                if presses[index].direction == direction {
+                    presses[index].direction_frames += frames;
+                } else {
+                    open_direction = None;
+                }
+            }
+
+            if let Some(index) = open_button {
+                let buttons = presses[index].button as usize;
+                if held & buttons == buttons {
+                    presses[index].button_frames += frames;
+                } else {
+                    open_button = None;
+                }
+            }
+
+            previous = (direction, button);
+        }
+
+        Ok(Self { player, presses })
+    }
+
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
+    pub fn presses(&self) -> &[Press] {
+        &self.presses
+    }
+
+    /// Every press written out, separated by commas
+    pub fn notation(&self) -> String {
+        self.presses
+            .iter()
+            .map(|press| press.notation())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.notation())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::{Character, Facing};
+    use crate::states::player_state::{PlayerInfo, PlayerState};
+
+    /// Consecutive frames of player one holding each direction and button
+    /// while facing the given way
+    fn frames(inputs: &[(InputDirection, InputButton, Facing)]) -> Vec<RoundState> {
+        inputs
+            .iter()
+            .enumerate()
+            .map(|(frame, (direction, button, facing))| {
+                let info = || PlayerInfo { screen_name: None };
+                let mut one = PlayerState::blank(Player::One, Character::Law);
+                one.input_direction = *direction as u16;
+                one.input_attack = *button as u16;
+                one.facing = match facing {
+                    Facing::Left => 0,
+                    Facing::Right => 1,
+                };
+
+                RoundState::new(1, frame as u128, (info(), info()), (one, PlayerState::blank(Player::Two, Character::Paul)))
+            })
+            .collect()
+    }
+
+    /// `count` frames of the same inputs, facing right
+    fn hold(direction: InputDirection, button: InputButton, count: usize) -> Vec<(InputDirection, InputButton, Facing)> {
+        vec![(direction, button, Facing::Right); count]
+    }
+
+    fn notation(inputs: &[(InputDirection, InputButton, Facing)]) -> String {
+        Transcript::new(&frames(inputs), Player::One).unwrap().notation()
+    }
+
+    #[test]
+    fn buttons_pressed_right_after_a_direction_merge_with_it() {
+        let merged = [hold(InputDirection::DownForward, InputButton::None, 2), hold(InputDirection::DownForward, InputButton::One, 2)].concat();
+        assert_eq!(notation(&merged), "d/f+1");
+
+        // Too late to be part of the same press
+        let separate = [hold(InputDirection::Forward, InputButton::None, 4), hold(InputDirection::Forward, InputButton::Two, 2)].concat();
+        assert_eq!(notation(&separate), "f, f+2");
+
+        // Buttons added while the first ones are held join the press
+        let chord = [hold(InputDirection::Neutral, InputButton::One, 1), hold(InputDirection::Neutral, InputButton::OnePlusTwo, 2)].concat();
+        assert_eq!(notation(&chord), "1+2");
+    }
+
+    #[test]
+    fn neutral_gaps_split_repeated_inputs() {
+        let dash = [
+            hold(InputDirection::Forward, InputButton::None, 2),
+            hold(InputDirection::Neutral, InputButton::None, 2),
+            hold(InputDirection::Forward, InputButton::None, 2),
+        ]
+        .concat();
+        assert_eq!(notation(&dash), "f, f");
+
+        let jabs = [
+            hold(InputDirection::Neutral, InputButton::One, 3),
+            hold(InputDirection::Neutral, InputButton::None, 5),
+            hold(InputDirection::Neutral, InputButton::One, 3),
+        ]
+        .concat();
+        assert_eq!(notation(&jabs), "1, 1");
+
+        // Nothing is written for frames spent in neutral
+        assert_eq!(notation(&hold(InputDirection::Neutral, InputButton::None, 30)), "");
+    }
+
+    #[test]
+    fn held_inputs_are_written_as_held() {
+        let inputs = [hold(InputDirection::Down, InputButton::None, 2), hold(InputDirection::Down, InputButton::Four, HOLD_FRAMES as usize)].concat();
+        let transcript = Transcript::new(&frames(&inputs), Player::One).unwrap();
+
+        assert_eq!(transcript.notation(), "D+4*");
+        assert_eq!(transcript.presses()[0].direction_frames, HOLD_FRAMES + 2);
+        assert_eq!(transcript.presses()[0].button_frames, HOLD_FRAMES);
+    }
+
+    #[test]
+    fn directions_held_across_a_side_switch_stay_one_press() {
+        // Recorded directions are relative to the facing, so back stays back
+        // when the players switch sides halfway through
+        let inputs = [
+            vec![(InputDirection::Back, InputButton::None, Facing::Right); 6],
+            vec![(InputDirection::Back, InputButton::None, Facing::Left); 6],
+        ]
+        .concat();
+        let transcript = Transcript::new(&frames(&inputs), Player::One).unwrap();
+
+        assert_eq!(transcript.notation(), "B");
+        assert_eq!(transcript.presses()[0].direction_frames, 12);
+    }
+
+    #[test]
+    fn presses_read_the_same_from_either_side() {
+        let moves = [
+            (InputDirection::Forward, InputButton::None),
+            (InputDirection::Neutral, InputButton::None),
+            (InputDirection::Forward, InputButton::Two),
+            (InputDirection::Neutral, InputButton::None),
+            (InputDirection::UpBack, InputButton::Three),
+        ];
+        let facing = |facing: Facing| -> Vec<_> { moves.iter().map(|(d, b)| (*d, *b, facing)).collect() };
+
+        assert_eq!(notation(&facing(Facing::Right)), "f, f+2, u/b+3");
+        assert_eq!(notation(&facing(Facing::Left)), notation(&facing(Facing::Right)));
+    }
+}
@@ -0,0 +1,297 @@
+//! Outcomes of attacks and throws, derived from consecutive frames.
+//!
+//! Each player's current move is tracked from the frame it starts, and the
+//! first outcome of an attacking move is reported once:
+//!
+//! - a hit when the opponent's damage goes up, or a counter hit when the
+//!   opponent was in the startup or active frames of an attack on the frame
+//!   before
+//! - a block when the opponent goes into a new move during the attack's
+//!   active frames without taking damage, while guarding with neutral or back
+//! - a whiff when the move ends without connecting
+//!
+//! Throws are reported when the throwing flag gets set, followed by a throw
+//! break if they end without the opponent taking damage. Knockdowns are
+//! reported when a player lands on the ground, with the opponent as the
+//! attacker.
+//!
+//! Blocks and counter hits need the active frames of moves, so neither is
+//! reported with offset tables that don't include them.
+
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use crate::globals::{AttackPhase, InputDirection, Player};
+use crate::states::game_state::RoundState;
+use crate::states::player_state::PlayerState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EventKind {
+    Hit,
+    CounterHit,
+    Block,
+    Whiff,
+    Throw,
+    ThrowBreak,
+    Knockdown,
+}
+
+/// Outcome of an attack
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub round: u8,
+    /// Round frame the outcome happened on
+    pub frame: u128,
+    pub attacker: Player,
+    pub kind: EventKind,
+    /// Attacker's move, as of the frame the attack started
+    pub move_id: u32,
+    /// Damage dealt by hits
+    pub damage: u32,
+}
+
+/// Turns frames into events one at a time, so it can follow a live capture
+/// as well as a recorded round
+#[derive(Default)]
+pub struct EventExtractor {
+    previous: Option<RoundState>,
+    /// Attacks of player one and two that haven't ended yet
+    attacks: [Option<Attack>; 2],
+    /// Throws of player one and two that haven't ended yet
+    throws: [Option<Throw>; 2],
+}
+
+#[derive(Clone, Copy)]
+struct Attack {
+    move_id: u32,
+    connected: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Throw {
+    move_id: u32,
+    /// Damage the opponent had received when the throw started
+    damage_before: u32,
+}
+
+impl EventExtractor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events that happened on `state`, which should directly follow the
+    /// previous state passed in
+    pub fn next(&mut self, state: &RoundState) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        if let Some(previous) = self.previous.take() {
+            // A new round starts over without anything in progress
+            if previous.get_round() != state.get_round() {
+                *self = Self::default();
+            } else {
+                for (index, attacker) in [Player::One, Player::Two].iter().enumerate() {
+                    self.player_events(index, *attacker, &previous, state, &mut events);
+                }
+            }
+        }
+
+        self.previous = Some(state.clone());
+        events
+    }
+
+    fn player_events(
+        &mut self,
+        index: usize,
+        attacker: Player,
+        previous: &RoundState,
+        state: &RoundState,
+        events: &mut Vec<Event>,
+    ) {
+        let current = state.get_player_state(attacker);
+        let before = previous.get_player_state(attacker);
+        let defender = state.get_player_state(!attacker);
+        let defender_before = previous.get_player_state(!attacker);
+
+        let mut event = |kind, move_id, damage| {
+            events.push(Event {
+                round: state.get_round(),
+                frame: state.get_round_frame(),
+                attacker,
+                kind,
+                move_id,
+                damage,
+            })
+        };
+        let damage = defender.damage_received().saturating_sub(defender_before.damage_received());
+
+        if current.throwing() && !before.throwing() {
+            event(EventKind::Throw, current.move_id(), 0);
+            self.throws[index] = Some(Throw {
+                move_id: current.move_id(),
+                damage_before: defender_before.damage_received(),
+            });
+        } else if !current.throwing() && before.throwing() {
+            if let Some(throw) = self.throws[index].take() {
+                if defender.damage_received() == throw.damage_before {
+                    event(EventKind::ThrowBreak, throw.move_id, 0);
+                }
+            }
+        }
+
+        let new_move = current.move_id() != before.move_id() || current.move_frame() < before.move_frame();
+        if new_move || !current.is_attacking() {
+            if let Some(attack) = self.attacks[index].take() {
+                if !attack.connected {
+                    event(EventKind::Whiff, attack.move_id, 0);
+                }
+            }
+        }
+
+        if current.is_attacking() && self.attacks[index].is_none() {
+            self.attacks[index] = Some(Attack {
+                move_id: current.move_id(),
+                connected: false,
+            });
+        }
+
+        if let Some(attack) = self.attacks[index].as_mut().filter(|attack| !attack.connected) {
+            if current.throwing() {
+                // Reported as a throw instead
+                attack.connected = true;
+            } else if damage > 0 {
+                let kind = if is_counterable(defender_before) {
+                    EventKind::CounterHit
+                } else {
+                    EventKind::Hit
+                };
+
+                event(kind, attack.move_id, damage);
+                attack.connected = true;
+            } else if current.attack_phase() == Some(AttackPhase::Active)
+                && defender.damage_received() == defender_before.damage_received()
+                && defender.move_id() != defender_before.move_id()
+                && !defender.is_attacking()
+                && is_guarding(defender)
+            {
+                event(EventKind::Block, attack.move_id, 0);
+                attack.connected = true;
+            }
+        }
+
+        if defender.ground_state().is_some() && defender_before.ground_state().is_none() {
+            event(EventKind::Knockdown, current.move_id(), 0);
+        }
+    }
+}
+
+/// Every event over consecutive frames of a round
+pub fn extract(states: &[RoundState]) -> Vec<Event> {
+    let mut extractor = EventExtractor::new();
+    states.iter().flat_map(|state| extractor.next(state)).collect()
+}
+
+/// Whether a hit on the player is a counter hit, i.e. they're attacking and
+/// haven't reached the recovery of their move yet
+fn is_counterable(player: &PlayerState) -> bool {
+    matches!(player.attack_phase(), Some(AttackPhase::Startup) | Some(AttackPhase::Active))
+}
+
+/// Whether the player holds a direction that blocks, standing or crouching
+fn is_guarding(player: &PlayerState) -> bool {
+    matches!(
+        InputDirection::try_from(player.input_direction() as usize),
+        Ok(InputDirection::Neutral) | Ok(InputDirection::Back) | Ok(InputDirection::DownBack)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::Character;
+    use crate::states::player_state::PlayerInfo;
+
+    const JAB: u32 = 100;
+    const BLOCKSTUN: u32 = 200;
+    const HITSTUN: u32 = 300;
+    const BACK: u16 = InputDirection::Back as u16;
+
+    fn state(frame: u128, attacker: PlayerState, defender: PlayerState) -> RoundState {
+        let info = || PlayerInfo { screen_name: None };
+        RoundState::new(1, frame, (info(), info()), (attacker, defender))
+    }
+
+    fn idle(player: Player) -> PlayerState {
+        let mut state = PlayerState::blank(player, Character::NotSelected);
+        state.input_direction = InputDirection::Neutral as u16;
+        state
+    }
+
+    /// Player one doing a jab, active on frames 10 to 12
+    fn jab(move_frame: u32) -> PlayerState {
+        let mut state = idle(Player::One);
+        state.move_id = JAB;
+        state.move_frame = move_frame;
+        state.attack_flags = 1;
+        state.active_start = 10;
+        state.active_end = 12;
+        state
+    }
+
+    fn defender(move_id: u32, input_direction: u16, damage_received: u32) -> PlayerState {
+        let mut state = idle(Player::Two);
+        state.move_id = move_id;
+        state.input_direction = input_direction;
+        state.damage_received = damage_received;
+        state
+    }
+
+    fn kinds(states: &[RoundState]) -> Vec<(Player, EventKind)> {
+        extract(states).iter().map(|e| (e.attacker, e.kind)).collect()
+    }
+
+    #[test]
+    fn blocks_need_active_frames_and_no_damage() {
+        let blocked = [
+            state(0, jab(9), defender(0, BACK, 0)),
+            state(1, jab(10), defender(BLOCKSTUN, BACK, 0)),
+        ];
+        assert_eq!(kinds(&blocked), vec![(Player::One, EventKind::Block)]);
+
+        // Backing off during the attack's startup isn't a block
+        let startup = [
+            state(0, jab(5), defender(0, BACK, 0)),
+            state(1, jab(6), defender(BLOCKSTUN, BACK, 0)),
+        ];
+        assert!(kinds(&startup).is_empty());
+
+        // Nor is the defender changing moves after the attack's active frames
+        let recovery = [
+            state(0, jab(13), defender(0, BACK, 0)),
+            state(1, jab(14), defender(BLOCKSTUN, BACK, 0)),
+        ];
+        assert!(kinds(&recovery).is_empty());
+    }
+
+    #[test]
+    fn counter_hits_need_the_defender_before_recovery() {
+        let mut attacking = jab(4);
+        attacking.player = Player::Two;
+        let mut recovering = jab(20);
+        recovering.player = Player::Two;
+
+        let counter_hit = [
+            state(0, jab(9), attacking),
+            state(1, jab(10), defender(HITSTUN, 0, 10)),
+        ];
+        assert!(kinds(&counter_hit).contains(&(Player::One, EventKind::CounterHit)));
+
+        let hit = [
+            state(0, jab(9), recovering),
+            state(1, jab(10), defender(HITSTUN, 0, 10)),
+        ];
+        let kinds = kinds(&hit);
+        assert!(kinds.contains(&(Player::One, EventKind::Hit)));
+        assert!(!kinds.contains(&(Player::One, EventKind::CounterHit)));
+    }
+}
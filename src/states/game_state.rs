@@ -1,8 +1,10 @@
-use read_process_memory::*;
+use std::convert::TryFrom;
+
 use serde::{Deserialize, Serialize};
 
-use crate::globals::{self, Player};
-use crate::memory::MemoryModel;
+use crate::globals::{self, Character, Player};
+use crate::memory::{MemoryBackend, MemoryModel, MemoryReadErrors};
+use crate::states::loop_state::TIME_TO_SLEEP_MS;
 use crate::states::player_state::{PlayerState, PlayerInfo};
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -15,6 +17,20 @@ pub struct RoundState {
 }
 
 impl RoundState {
+    pub fn new(
+        round: u8,
+        round_frame: u128,
+        player_info: (PlayerInfo, PlayerInfo),
+        player_state: (PlayerState, PlayerState)
+    ) -> Self {
+        Self {
+            round,
+            round_frame,
+            player_info,
+            player_state
+        }
+    }
+
     pub fn get_round(&self) -> u8 {
         self.round
     }
@@ -30,16 +46,38 @@ impl RoundState {
         }
     }
 
+    pub fn get_player_info(&self, player: Player) -> &PlayerInfo {
+        match player {
+            Player::One => &self.player_info.0,
+            Player::Two => &self.player_info.1
+        }
+    }
+
     /// Sets the frame count for the current round.
     pub fn update_round_frame(&mut self, round_frame: u128) {
         self.round_frame = round_frame;
     }
 
-    pub fn update_player_state<M: MemoryModel>(&mut self, memory: &M, player: Player) {
-        match player {
-            Player::One => &self.player_state.0.update(memory),
-            Player::Two => &self.player_state.1.update(memory)
+    /// Reads both players for the current frame. Facings are read once up
+    /// front, since each player's ground state depends on both of them, and
+    /// the shared throw timer once both players are decoded.
+    pub fn update_players<M: MemoryModel>(&mut self, memory: &M) -> Result<(), Box<dyn std::error::Error>> {
+        let facing = (memory.facing(Player::One)?, memory.facing(Player::Two)?);
+
+        self.player_state.0.update(memory, facing.0, facing.1)?;
+        self.player_state.1.update(memory, facing.1, facing.0)?;
+
+        // The timer only counts for the player being thrown
+        let (one, two) = &mut self.player_state;
+        let throw_timer = if one.throwing || two.throwing {
+            memory.throw_timer()?
+        } else {
+            0
         };
+        one.throw_break_window = if two.throwing { throw_timer } else { 0 };
+        two.throw_break_window = if one.throwing { throw_timer } else { 0 };
+
+        Ok(())
     }
 }
 
@@ -52,49 +90,80 @@ pub struct GameState<M> {
 }
 
 impl<M: MemoryModel> GameState<M> {
-    pub fn new(handle: ProcessHandle) -> Self {
+    pub fn new(memory: M) -> Self {
         Self {
-            memory: M::new(handle),
+            memory,
             state: None
         }
     }
 
-    pub fn start(&mut self) {
-        while self.memory.round_frame().unwrap() != 0 {
-            std::thread::sleep(std::time::Duration::from_secs_f64(1f64 / 120f64));
+    /// Waits for a round to begin and captures its first frame.
+    /// Returns `false` if the memory backend ran out of frames first.
+    pub fn start(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        loop {
+            match self.memory.round_frame() {
+                Ok(0) => break,
+                // The round timer can't always be read outside of a match
+                Err(e) if MemoryReadErrors::is_process_gone(e.as_ref()) => return Err(e),
+                _ => (),
+            };
+
+            if !self.advance() {
+                return Ok(false);
+            }
         }
 
         self.state = Some(RoundState {
-            round: self.memory.round().unwrap().into(),
-            round_frame: self.memory.round_frame().unwrap().into(),
+            round: self.memory.round()?.into(),
+            round_frame: self.memory.round_frame()?.into(),
 
             player_info: (
                 PlayerInfo { screen_name: None },
                 PlayerInfo { screen_name: None }
             ),
+            // Filled in by the update below
             player_state: (
-                PlayerState::new(&self.memory, Player::One),
-                PlayerState::new(&self.memory, Player::Two)
+                PlayerState::blank(Player::One, Character::NotSelected),
+                PlayerState::blank(Player::Two, Character::NotSelected)
             )
         });
 
-        self.update().unwrap();
+        self.update()?;
+        Ok(true)
+    }
+
+    /// Moves the memory backend onto its next frame, sleeping in between
+    /// reads when attached to a live game.
+    /// Returns `false` once the backend has no more frames to offer.
+    pub fn advance(&self) -> bool {
+        let backend = self.memory.backend();
+        if backend.is_live() {
+            std::thread::sleep(std::time::Duration::from_secs_f64(TIME_TO_SLEEP_MS));
+        }
+
+        backend.advance()
     }
 
     pub fn update(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let state: &mut RoundState = self.state.as_mut().expect("Update requires `start()` to be called first");
 
         state.update_round_frame(self.memory.round_frame()?.into());
-        state.update_player_state::<M>(&self.memory, Player::One);
-        state.update_player_state::<M>(&self.memory, Player::Two);
+        state.update_players(&self.memory)?;
+
+        let button = |attack: u16| {
+            globals::InputButton::try_from(attack as usize).map_or("?", |b| b.to_str())
+        };
+        let direction = |direction: u16| {
+            globals::InputDirection::try_from(direction as usize).map_or("?", |d| d.to_str())
+        };
 
         println!(
             "Frame: {}\tP1: {}, {} \t P2: {}, {}",
             state.round_frame,
-            globals::InputButton::from(state.player_state.0.input_attack as usize).to_str(),
-            globals::InputDirection::from(state.player_state.0.input_direction as usize).to_str(),
-            globals::InputButton::from(state.player_state.1.input_attack as usize).to_str(),
-            globals::InputDirection::from(state.player_state.1.input_direction as usize).to_str()
+            button(state.player_state.0.input_attack),
+            direction(state.player_state.0.input_direction),
+            button(state.player_state.1.input_attack),
+            direction(state.player_state.1.input_direction)
         );
 
         Ok(())
@@ -111,3 +180,182 @@ impl<M: MemoryModel> GameState<M> {
         self.state.as_ref().unwrap().clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::io;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::*;
+use crate::globals::{Face, Facing, Feet};
+    use crate::memory::backends::{MemorySnapshot, SnapshotReplay};
+    use crate::memory::models::{OffsetModel, OffsetTable};
+
+    const MODULE: usize = 0x1_4000_0000;
+    const PLAYER_ONE: usize = 0x1000;
+    const PLAYER_TWO: usize = 0x2000;
+    /// Size of the player blocks of `OFFSETS`
+    const BLOCK_SIZE: usize = 0x18;
+    /// Reads of a frame: one per facing and one per player block
+    const PLAYER_READS: usize = 4;
+    /// Reads of the throw timer shared by both players
+    const THROW_TIMER_READS: usize = 1;
+
+    const OFFSETS: &str = r#"
+        version = "test"
+
+        [players]
+        player_one_base = 0x1000
+        player_two_base = 0x2000
+        player_one_facing = 0x3000
+        player_two_facing = 0x3004
+        character_id = 0x0
+        coordinate_x = 0x4
+        coordinate_y = 0x8
+        coordinate_z = 0xC
+        health = 0x10
+        input_attack = 0x14
+        input_direction = 0x16
+
+        [round]
+        count = 0x4000
+        timer = 0x4004
+    "#;
+
+    /// Counts the reads made through the snapshots it wraps
+    struct CountingBackend {
+        replay: SnapshotReplay,
+        reads: Cell<usize>,
+    }
+
+    impl MemoryBackend for CountingBackend {
+        fn copy(&self, address: usize, length: usize) -> io::Result<Vec<u8>> {
+            self.reads.set(self.reads.get() + 1);
+            self.replay.copy(address, length)
+        }
+
+        fn advance(&self) -> bool {
+            self.replay.advance()
+        }
+    }
+
+    fn player(character: Character, x: f32, damage: u32, attack: u16, direction: u16) -> Vec<u8> {
+        let mut bytes = vec![0; BLOCK_SIZE];
+        LittleEndian::write_u16(&mut bytes[0x0..], character as u16);
+        LittleEndian::write_f32(&mut bytes[0x4..], x);
+        LittleEndian::write_u32(&mut bytes[0x10..], damage);
+        LittleEndian::write_u16(&mut bytes[0x14..], attack);
+        LittleEndian::write_u16(&mut bytes[0x16..], direction);
+        bytes
+    }
+
+    /// Law on the left facing right, against Paul facing left. `extra`
+    /// holds a `u32` appended to each player block, past `BLOCK_SIZE`.
+    fn frame(damage: u32, extra: Option<(u32, u32)>) -> MemorySnapshot {
+        let mut one = player(Character::Law, 1.5, damage, 1, 16);
+        let mut two = player(Character::Paul, -1.5, 0, 0, 32);
+        if let Some((first, second)) = extra {
+            one.extend_from_slice(&first.to_le_bytes());
+            two.extend_from_slice(&second.to_le_bytes());
+        }
+
+        let mut snapshot = MemorySnapshot::new();
+        snapshot.insert(MODULE + PLAYER_ONE, one);
+        snapshot.insert(MODULE + PLAYER_TWO, two);
+        snapshot.insert(MODULE + 0x3000, vec![1, 0, 0, 0, 0, 0, 0, 0]);
+        snapshot
+    }
+
+    /// Model over `frames`, with `players` and `round` added to the player
+    /// and round offsets of `OFFSETS`
+    fn memory(players: &str, round: &str, frames: Vec<MemorySnapshot>) -> OffsetModel<CountingBackend> {
+        let offsets = format!("{}\n{}", OFFSETS.replace("[round]", &format!("{}\n[round]", players)), round);
+        let table = OffsetTable::from_toml_str(&offsets).unwrap();
+        let backend = CountingBackend {
+            replay: SnapshotReplay::new(frames),
+            reads: Cell::new(0),
+        };
+
+        OffsetModel::new(backend, table)
+    }
+
+    fn round_state() -> RoundState {
+        RoundState::new(
+            1,
+            0,
+            (PlayerInfo { screen_name: None }, PlayerInfo { screen_name: None }),
+            (
+                PlayerState::blank(Player::One, Character::NotSelected),
+                PlayerState::blank(Player::Two, Character::NotSelected),
+            ),
+        )
+    }
+
+    #[test]
+    fn reads_player_state_from_snapshots() {
+        let memory = memory("", "", vec![frame(0, None), frame(12, None)]);
+        let mut state = round_state();
+
+        state.update_players(&memory).unwrap();
+        let one = state.get_player_state(Player::One);
+        assert_eq!(one.character(), Character::Law);
+        assert_eq!(one.x(), 1.5);
+        assert_eq!(one.input_attack(), 1);
+        assert_eq!(one.input_direction(), 16);
+        assert_eq!(one.damage_received(), 0);
+        assert_eq!(one.facing(), Facing::Right);
+        assert_eq!(state.get_player_state(Player::Two).character(), Character::Paul);
+        assert_eq!(state.get_player_state(Player::Two).facing(), Facing::Left);
+        assert_eq!(memory.backend().reads.get(), PLAYER_READS);
+
+        assert!(memory.backend().advance());
+        state.update_players(&memory).unwrap();
+        assert_eq!(state.get_player_state(Player::One).damage_received(), 12);
+        assert!(!memory.backend().advance());
+    }
+
+    #[test]
+    fn ground_states_come_from_the_player_block() {
+        let memory = memory("move_state = 0x18", "", vec![frame(0, Some((12, 0)))]);
+        let mut state = round_state();
+        state.update_players(&memory).unwrap();
+
+        // Facing right towards an opponent facing left, lying face up
+        assert_eq!(state.get_player_state(Player::One).ground_state(), Some((Face::Up, Feet::Facing)));
+        assert_eq!(state.get_player_state(Player::Two).ground_state(), None);
+        // Nothing read on top of the player blocks
+        assert_eq!(memory.backend().reads.get(), PLAYER_READS);
+    }
+
+    #[test]
+    fn no_ground_state_or_wakeup_without_the_move_state_offset() {
+        // Face up move state right after player one's block, where a table
+        // with `move_state` would read it
+        let memory = memory("", "", vec![frame(0, Some((12, 0))), frame(0, Some((12, 0)))]);
+        let mut state = round_state();
+
+        for _ in 0..2 {
+            state.update_players(&memory).unwrap();
+            assert_eq!(state.get_player_state(Player::One).ground_state(), None);
+            assert_eq!(state.get_player_state(Player::One).wakeup(), None);
+            memory.backend().advance();
+        }
+    }
+
+    #[test]
+    fn throw_timer_is_read_once_for_the_player_being_thrown() {
+        let mut snapshot = frame(0, Some((1, 0)));
+        snapshot.insert(MODULE + 0x4008, vec![25, 0, 0, 0]);
+        let memory = memory("throwing = 0x18", "throw_timer = 0x4008", vec![snapshot]);
+
+        let mut state = round_state();
+        state.update_players(&memory).unwrap();
+
+        assert!(state.get_player_state(Player::One).throwing());
+        assert_eq!(state.get_player_state(Player::One).throw_break_window(), 0);
+        assert_eq!(state.get_player_state(Player::Two).throw_break_window(), 25);
+        assert_eq!(memory.backend().reads.get(), PLAYER_READS + THROW_TIMER_READS);
+    }
+}
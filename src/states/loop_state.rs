@@ -1,35 +1,46 @@
-use std::fs::File;
-use std::io::prelude::*;
+use std::fs;
+use std::path::Path;
 use std::thread;
 
-use read_process_memory::ProcessHandle;
+use read_process_memory::Pid;
 
-use crate::globals;
-use crate::memory::MemoryModel;
+use crate::capture::{stream, CaptureErrors, CaptureWriter, StreamWriter};
+use crate::util;
+use crate::memory::{MemoryModel, MemoryReadErrors};
 use crate::states::game_state::GameState;
 use super::game_state::RoundState;
-use globals::Player;
+use super::match_state::{MatchState, RoundRecord, RoundResult};
+use super::output::{CaptureFormat, OutputConfig};
 
 /// Time to sleep in between loop checks
 pub const TIME_TO_SLEEP_MS: f64 = 1.0f64 / 120.0f64;
 
 pub struct LoopState<M> {
     round_states: Vec<RoundState>,
-    index: usize,
+    /// Rounds already finished in the current match
+    match_state: Option<MatchState>,
+    /// Partial capture the current match is streamed to
+    journal: Option<StreamWriter>,
+    /// Version of the game being captured, recorded in match headers
+    game_version: Option<String>,
+    output: OutputConfig,
 
     memory: std::marker::PhantomData<M>,
 }
 
 impl<M: MemoryModel> LoopState<M> {
-    pub fn new() -> Self {
+    pub fn new(output: OutputConfig) -> Self {
         //let game_data = builder.build();
 
         Self {
-            /// Pre-allocate 6000 frames of data,
-            /// taking into account rage art animations in a Tekken 7 
-            /// match using official TWT rules (excluding round-victory inputs)
+            // Pre-allocate 6000 frames of data,
+            // taking into account rage art animations in a Tekken 7
+            // match using official TWT rules (excluding round-victory inputs)
             round_states: Vec::with_capacity(6000),
-            index: 0,
+            match_state: None,
+            journal: None,
+            game_version: None,
+            output,
 
             memory: std::marker::PhantomData
             //replay_mode: replay.is_some(),
@@ -37,86 +48,256 @@ impl<M: MemoryModel> LoopState<M> {
     }
 
     fn previous_state(&self) -> Option<&RoundState> {
-        self.round_states.get(self.index - 1)
+        self.round_states.last()
     }
 
-    fn state(&self) -> &RoundState {
-        &self.round_states[self.index]
+    /// Captures rounds until the memory backend runs out of frames.
+    /// Reads that fail mid-match are skipped; only losing the game process
+    /// ends the capture with an error.
+    pub fn start(&mut self, memory: M) -> Result<(), Box<dyn std::error::Error>> {
+        self.start_capture(memory)
     }
 
-    /// Sleeps until the frame count has been updated
-    fn wait_to_proceed(&self) {
-        match (
-            self.previous_state().map(|s| s.get_round_frame()),
-            self.state().get_round_frame(),
-        ) {
-            (Some(previous_frame), current_frame) 
-            if (previous_frame == current_frame) => {
-                // Sleep every 1/120th of a second if we're still in the same frame so that we can save our processing power
-                thread::sleep(std::time::Duration::from_secs_f64(TIME_TO_SLEEP_MS));
-            }
-            _ => (),
-        };
-    }
+    /// Captures from the running game forever. Whenever the game process
+    /// exits, the round in progress is saved and the loop goes back to
+    /// waiting for Tekken to start, attaching to it again with `attach`.
+    /// Only stops when `attach` finds a game version without offsets.
+    pub fn start_live<F>(&mut self, mut attach: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(Pid) -> Result<M, Box<dyn std::error::Error>>,
+    {
+        loop {
+            let pid = util::wait_for_pid();
+
+            let memory = match attach(pid) {
+                Ok(memory) => memory,
+                // The same build is found again on every attempt
+                Err(e) if MemoryReadErrors::is_unsupported_version(e.as_ref()) => return Err(e),
+                Err(e) => {
+                    println!("Failed to attach to Tekken: {} - Retrying in 10s...", e);
+                    thread::sleep(std::time::Duration::from_secs(10));
+                    continue;
+                }
+            };
 
-    pub fn start(&mut self, handle: ProcessHandle) {
-        self.start_capture(handle);
+            match self.start(memory) {
+                Ok(()) => return Ok(()),
+                Err(e) => println!("Lost the Tekken process, waiting for it to restart: {}", e),
+            };
+        }
     }
 
-    fn start_capture(&mut self, handle: ProcessHandle) {
-        let mut game_state = GameState::<M>::new(handle);
+    fn start_capture(&mut self, memory: M) -> Result<(), Box<dyn std::error::Error>> {
+        self.game_version = Some(memory.game_version().to_string());
+        let mut game_state = GameState::<M>::new(memory);
 
         loop {
-            // Determine if we should sleep
-            self.wait_to_proceed();
-            game_state.update().unwrap();
+            match game_state.start() {
+                Ok(true) => break,
+                Ok(false) => return Ok(()),
+                Err(e) if MemoryReadErrors::is_process_gone(e.as_ref()) => return Err(e),
+                Err(e) => println!("Failed to start round, retrying: {}", e),
+            };
+
+            if !game_state.advance() {
+                return Ok(());
+            }
+        }
+
+        // Frames skipped in a row, reported once when reads start failing
+        // and once when they succeed again
+        let mut skipped = 0;
+        loop {
+            match game_state.update() {
+                Ok(()) => {
+                    if skipped > 0 {
+                        println!("Reads succeeded again after skipping {} frames", skipped);
+                        skipped = 0;
+                    }
+                    self.record(game_state.clone_round_state())
+                }
+                Err(e) if MemoryReadErrors::is_process_gone(e.as_ref()) => {
+                    self.finish_round(false);
+                    self.save_match();
+                    return Err(e);
+                }
+                Err(e) => {
+                    if skipped == 0 {
+                        println!("Skipping frames until reads succeed again: {}", e);
+                    }
+                    skipped += 1;
+                }
+            };
+
+            if !game_state.advance() {
+                break;
+            }
+        }
 
-            let current_round = self.state().get_round();
-            let current_frame = self.state().get_round_frame();
-            let previous_frame = self.previous_state()
-                .map(|s| s.get_round_frame());
+        // Offline backends end mid-round, so keep whatever was captured
+        self.finish_round(false);
+        self.save_match();
+        Ok(())
+    }
 
-            let round_state = game_state.round_state();
+    /// Adds a frame to the current round. The round ends once the frame count
+    /// goes back down, and the match is saved when the next round doesn't continue it
+    fn record(&mut self, state: RoundState) {
+        let current_frame = state.get_round_frame();
+        let previous_frame = self.previous_state()
+            .map(|s| s.get_round_frame());
 
+        match previous_frame {
             // If we're in the same frame, we'll want to wait until the next
-            if previous_frame.is_none() {
-                match round_state {
-                    Some(state) => self.round_states.push(state),
-                    None => ()
-                };
+            Some(previous_frame) if previous_frame == current_frame => (),
+            // Save match data when the frame count goes back down
+            Some(previous_frame) if current_frame < previous_frame => {
+                self.finish_round(true);
 
-                continue;
+                let continues = self.match_state.as_ref().map_or(true, |m| m.continues_with(&state));
+                if !continues {
+                    self.save_match();
+                }
+
+                self.push_state(state);
             }
+            _ => self.push_state(state),
+        };
+    }
 
-            let previous_frame = previous_frame.unwrap();
-            if previous_frame == current_frame {
-                continue;
+    /// Adds a frame to the current round and streams it to disk, starting
+    /// a new match with the first frame
+    fn push_state(&mut self, state: RoundState) {
+        if self.match_state.is_none() {
+            let match_state = MatchState::new(&state, self.game_version.clone());
+
+            self.journal = StreamWriter::create(self.output.directory(), match_state.header())
+                .map_err(|e| println!("Failed to create a partial capture, the match will only be saved once over: {}", e))
+                .ok();
+            self.match_state = Some(match_state);
+        }
+
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(e) = journal.write_frame(&state) {
+                println!("Failed to write to {}, the match will only be saved once over: {}", journal.path().display(), e);
+                self.journal = None;
             }
+        }
 
-            // Only update the batch once we have a new frame to advance
-            let state = round_state.expect(&format!("Expected game state at frame {}, found None", current_frame));
-            self.round_states.push(state);
+        self.round_states.push(state);
+    }
 
-            // Save match data when the round count changes
-            if current_frame < previous_frame {
-                let round_result = serde_json::to_string(&self.round_states).unwrap();
-                let file_name = format!(
-                    "C:/Users/gsala/Documents/Tekken7Replays/{}_vs_{}_{}_{}.json",
-                    self.state().get_player_state(Player::One).character().to_string(),
-                    self.state().get_player_state(Player::Two).character().to_string(),
-                    current_round,
-                    uuid::Uuid::new_v4()
-                );
+    /// Moves the frames of the current round into the match
+    fn finish_round(&mut self, ended: bool) {
+        let states = std::mem::replace(&mut self.round_states, Vec::with_capacity(6000));
+        let result = match RoundResult::from_states(&states, ended) {
+            Some(result) => result,
+            None => return,
+        };
+
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(e) = journal.end_round(&result) {
+                println!("Failed to write to {}, the match will only be saved once over: {}", journal.path().display(), e);
+                self.journal = None;
+            }
+        }
+
+        if let Some(match_state) = self.match_state.as_mut() {
+            match_state.push_record(RoundRecord { result, states });
+        }
+    }
+
+    /// Writes the current match out and starts over with a new one
+    fn save_match(&mut self) {
+        let journal = self.journal.take();
+        let match_state = match self.match_state.take() {
+            Some(match_state) => match_state,
+            None => return,
+        };
+
+        let last_state = match match_state.last_state() {
+            Some(state) => state,
+            None => return,
+        };
+
+        let file_name = self.output.path_for(last_state, self.output.extension());
+        println!("Creating file: {}", file_name.display());
+
+        let saved = match (self.output.format(), journal) {
+            // The partial capture already holds the whole match
+            (CaptureFormat::Binary, Some(journal)) => journal.finalize(&file_name).map_err(|e| e.into()),
+            (_, journal) => {
+                let saved = self.write_match(&match_state, &file_name);
+                if let (Ok(()), Some(journal)) = (&saved, journal) {
+                    let _ = journal.discard();
+                }
+                saved
+            }
+        };
+
+        if let Err(e) = saved {
+            println!("Failed to save match to {}: {}", file_name.display(), e);
+        }
+    }
+
+    fn write_match(&self, match_state: &MatchState, file_name: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(self.output.directory())?;
+
+        stream::write_atomically(file_name, |w| {
+            match self.output.format() {
+                CaptureFormat::Binary => {
+                    CaptureWriter::write_match(w, match_state)?;
+                }
+                CaptureFormat::Json => serde_json::to_writer(w, match_state)?,
+            };
+            Ok(())
+        })
+    }
+
+    /// Salvages the partial captures left in the output directory by a
+    /// capture that was killed, saving what they hold as regular matches
+    pub fn recover(&mut self) {
+        let partials = match stream::partial_files(self.output.directory()) {
+            Ok(partials) => partials,
+            Err(e) => {
+                println!("Failed to look for partial captures: {}", e);
+                return;
+            }
+        };
 
-                println!("Creating file: {}", file_name);
+        for partial in partials {
+            match stream::salvage(&partial) {
+                Ok(match_state) => {
+                    let last_state = match match_state.last_state() {
+                        Some(state) => state,
+                        None => continue,
+                    };
+                    let file_name = self.output.path_for(last_state, self.output.extension());
 
-                let mut file = File::create(file_name).expect("Failed to create file");
+                    if let Err(e) = self.write_match(&match_state, &file_name) {
+                        println!("Failed to recover {}, leaving it in place: {}", partial.display(), e);
+                        continue;
+                    }
 
-                file.write(&round_result.as_bytes())
-                    .expect("Failed to write buffer to file");
-                file.flush().expect("Failed to flush file");
+                    println!(
+                        "Recovered {} round(s) from {} into {}",
+                        match_state.rounds().len(),
+                        partial.display(),
+                        file_name.display()
+                    );
+                }
+                // Nothing to save, the capture was killed before its first frame was synced
+                Err(e) if matches!(e.downcast_ref::<CaptureErrors>(), Some(CaptureErrors::NoFrames)) => {
+                    println!("Removing {}: {}", partial.display(), e);
+                }
+                Err(e) => {
+                    println!("Failed to recover {}, leaving it in place: {}", partial.display(), e);
+                    continue;
+                }
+            };
 
-                self.round_states.clear();
+            if let Err(e) = fs::remove_file(&partial) {
+                println!("Failed to remove {}: {}", partial.display(), e);
             }
         }
     }
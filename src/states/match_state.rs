@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+
+use crate::globals::{Character, Player, Side};
+use crate::states::game_state::RoundState;
+
+/// How a round came to an end
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RoundFinish {
+    KnockOut,
+    TimeOut,
+    /// Capture stopped before the round was decided
+    Unfinished,
+}
+
+/// Outcome of a single round
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoundResult {
+    pub round: u8,
+    /// `None` on draws and unfinished rounds
+    pub winner: Option<Player>,
+    pub finish: RoundFinish,
+    /// Sides of the screen player one and two started the round on
+    pub starting_sides: (Side, Side),
+    /// Health player one and two had left on the last captured frame
+    pub final_health: (u32, u32),
+    /// Number of frames captured in the round
+    pub frames: usize,
+}
+
+/// Every frame captured in a round along with its outcome
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoundRecord {
+    pub result: RoundResult,
+    pub states: Vec<RoundState>,
+}
+
+/// Summary of the match written at the top of its document
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MatchHeader {
+    /// Version of the game the match was captured from, unknown for old captures
+    #[serde(default)]
+    pub game_version: Option<String>,
+    pub characters: (Character, Character),
+    pub screen_names: (Option<String>, Option<String>),
+    /// Seconds since the Unix epoch when the first round was captured
+    pub started_at: u64,
+    pub rounds_won: (u8, u8),
+    /// `None` while rounds are tied
+    pub winner: Option<Player>,
+}
+
+/// Rounds played in a single match, serialized as one document
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MatchState {
+    header: MatchHeader,
+    rounds: Vec<RoundRecord>,
+}
+
+impl RoundResult {
+    /// Decides the outcome of a round from its frames. `ended` tells whether
+    /// the next round started, as opposed to the capture stopping mid-round.
+    pub fn from_states(states: &[RoundState], ended: bool) -> Option<Self> {
+        let first = states.first()?;
+        let last = states.last()?;
+
+        let health = |player| last.get_player_state(player).health();
+        let final_health = (health(Player::One), health(Player::Two));
+
+        let finish = if final_health.0 == 0 || final_health.1 == 0 {
+            RoundFinish::KnockOut
+        } else if ended {
+            RoundFinish::TimeOut
+        } else {
+            RoundFinish::Unfinished
+        };
+
+        let winner = match finish {
+            RoundFinish::Unfinished => None,
+            _ if final_health.0 > final_health.1 => Some(Player::One),
+            _ if final_health.1 > final_health.0 => Some(Player::Two),
+            _ => None,
+        };
+
+        let starting_sides = if first.get_player_state(Player::One).x()
+            <= first.get_player_state(Player::Two).x()
+        {
+            (Side::Left, Side::Right)
+        } else {
+            (Side::Right, Side::Left)
+        };
+
+        Some(Self {
+            round: last.get_round(),
+            winner,
+            finish,
+            starting_sides,
+            final_health,
+            frames: states.len(),
+        })
+    }
+}
+
+impl MatchState {
+    /// Starts a match from the first frame captured in it
+    pub fn new(first: &RoundState, game_version: Option<String>) -> Self {
+        let character = |player| first.get_player_state(player).character();
+        let screen_name = |player| first.get_player_info(player).screen_name().clone();
+
+        Self {
+            header: MatchHeader {
+                game_version,
+                characters: (character(Player::One), character(Player::Two)),
+                screen_names: (screen_name(Player::One), screen_name(Player::Two)),
+                started_at: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+                rounds_won: (0, 0),
+                winner: None,
+            },
+            rounds: Vec::new(),
+        }
+    }
+
+    /// Empty match with the given header, with round wins counted from scratch
+    pub fn with_header(mut header: MatchHeader) -> Self {
+        header.rounds_won = (0, 0);
+        header.winner = None;
+
+        Self {
+            header,
+            rounds: Vec::new(),
+        }
+    }
+
+    pub fn header(&self) -> &MatchHeader {
+        &self.header
+    }
+
+    pub fn rounds(&self) -> &[RoundRecord] {
+        &self.rounds
+    }
+
+    /// Last frame captured in the match
+    pub fn last_state(&self) -> Option<&RoundState> {
+        self.rounds.last().and_then(|r| r.states.last())
+    }
+
+    /// Whether `state` continues this match rather than starting a new one.
+    /// A match is over once the characters change or the round count goes
+    /// back down.
+    pub fn continues_with(&self, state: &RoundState) -> bool {
+        let same_characters = self.header.characters
+            == (
+                state.get_player_state(Player::One).character(),
+                state.get_player_state(Player::Two).character(),
+            );
+
+        let later_round = self
+            .rounds
+            .last()
+            .map_or(true, |r| state.get_round() > r.result.round);
+
+        same_characters && later_round
+    }
+
+    /// Adds the frames of a round, updating the match header with its result
+    pub fn push_round(&mut self, states: Vec<RoundState>, ended: bool) {
+        if let Some(result) = RoundResult::from_states(&states, ended) {
+            self.push_record(RoundRecord { result, states });
+        }
+    }
+
+    /// Adds a round whose result is already decided
+    pub fn push_record(&mut self, record: RoundRecord) {
+        match record.result.winner {
+            Some(Player::One) => self.header.rounds_won.0 += 1,
+            Some(Player::Two) => self.header.rounds_won.1 += 1,
+            None => (),
+        };
+
+        let (p1, p2) = self.header.rounds_won;
+        self.header.winner = if p1 > p2 {
+            Some(Player::One)
+        } else if p2 > p1 {
+            Some(Player::Two)
+        } else {
+            None
+        };
+
+        self.rounds.push(record);
+    }
+}
@@ -1,3 +1,6 @@
+pub mod events;
 pub mod game_state;
 pub mod loop_state;
+pub mod match_state;
+pub mod output;
 pub mod player_state;
@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use crate::globals::Player;
+use crate::states::game_state::RoundState;
+
+/// File name used when no template is given, matching the original capture files
+pub const DEFAULT_TEMPLATE: &'static str = "{p1}_vs_{p2}_{round}_{uuid}";
+
+/// File format captured matches are written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureFormat {
+    /// Compact capture container, see [`crate::capture`]
+    Binary,
+    /// One JSON match document
+    Json,
+}
+
+/// Where captured rounds are written and how their files are named.
+///
+/// The template supports the placeholders `{p1}` and `{p2}` (characters),
+/// `{p1_name}` and `{p2_name}` (screen names), `{round}` (last round
+/// captured), `{timestamp}` (seconds since the Unix epoch) and `{uuid}`.
+#[derive(Clone)]
+pub struct OutputConfig {
+    directory: PathBuf,
+    template: String,
+    format: CaptureFormat,
+}
+
+impl OutputConfig {
+    pub fn new<P: Into<PathBuf>>(directory: P, template: Option<&str>, format: CaptureFormat) -> Self {
+        Self {
+            directory: directory.into(),
+            template: template.unwrap_or(DEFAULT_TEMPLATE).to_string(),
+            format,
+        }
+    }
+
+    pub fn format(&self) -> CaptureFormat {
+        self.format
+    }
+
+    /// Extension of the files written in the configured format
+    pub fn extension(&self) -> &'static str {
+        match self.format {
+            CaptureFormat::Binary => crate::capture::EXTENSION,
+            CaptureFormat::Json => "json",
+        }
+    }
+
+    pub fn directory(&self) -> &PathBuf {
+        &self.directory
+    }
+
+    /// Path of the file `state` is saved to, with the given extension
+    pub fn path_for(&self, state: &RoundState, extension: &str) -> PathBuf {
+        let screen_name = |player| {
+            state
+                .get_player_info(player)
+                .screen_name()
+                .clone()
+                .unwrap_or_else(|| format!("{:?}", player))
+        };
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let file_name = self
+            .template
+            .replace("{p1}", &state.get_player_state(Player::One).character().to_string())
+            .replace("{p2}", &state.get_player_state(Player::Two).character().to_string())
+            .replace("{p1_name}", &screen_name(Player::One))
+            .replace("{p2_name}", &screen_name(Player::Two))
+            .replace("{round}", &state.get_round().to_string())
+            .replace("{timestamp}", &timestamp.to_string())
+            .replace("{uuid}", &uuid::Uuid::new_v4().to_string());
+
+        // Appended rather than set with `with_extension`, which would cut
+        // off names containing dots such as `v3.33`
+        self.directory
+            .join(format!("{}.{}", sanitize(&file_name), extension))
+    }
+}
+
+/// Replaces characters that can't appear in file names
+fn sanitize(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::Character;
+    use crate::states::player_state::{PlayerInfo, PlayerState};
+
+    fn state() -> RoundState {
+        RoundState::new(
+            2,
+            0,
+            (PlayerInfo { screen_name: None }, PlayerInfo { screen_name: Some("a/b".into()) }),
+            (
+                PlayerState::blank(Player::One, Character::Law),
+                PlayerState::blank(Player::Two, Character::Paul),
+            ),
+        )
+    }
+
+    #[test]
+    fn names_keep_dots_and_replace_separators() {
+        let output = OutputConfig::new("captures", Some("v3.33_{p1_name}_{p2_name}_{round}"), CaptureFormat::Json);
+
+        assert_eq!(output.path_for(&state(), "json"), PathBuf::from("captures/v3.33_One_a_b_2.json"));
+    }
+}
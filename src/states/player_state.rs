@@ -1,6 +1,10 @@
-use crate::{memory::MemoryModel, globals::{Character, Player, Facing}};
+use std::convert::TryFrom;
+
+use byteorder::LittleEndian;
 use serde::{Deserialize, Serialize};
 
+use crate::{memory::MemoryModel, globals::{AttackPhase, Character, Player, Facing, GroundState, InputButton, InputDirection, Wakeup, MAX_HEALTH}};
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PlayerState {
     pub(crate) player: Player,
@@ -14,6 +18,34 @@ pub struct PlayerState {
     pub(crate) input_direction: u16,
     pub(crate) damage_received: u32,
     pub(crate) facing: u8,
+    #[serde(default)]
+    pub(crate) ground_state: Option<GroundState>,
+    #[serde(default)]
+    pub(crate) wakeup: Option<Wakeup>,
+
+    #[serde(default)]
+    pub(crate) move_id: u32,
+    #[serde(default)]
+    pub(crate) move_frame: u32,
+    #[serde(default)]
+    pub(crate) recovery_remaining: u32,
+    #[serde(default)]
+    pub(crate) attack_flags: u32,
+    #[serde(default)]
+    pub(crate) active_start: u32,
+    #[serde(default)]
+    pub(crate) active_end: u32,
+    #[serde(default)]
+    pub(crate) stance_flags: u32,
+
+    #[serde(default)]
+    pub(crate) rage: bool,
+    #[serde(default)]
+    pub(crate) combo_count: u32,
+    #[serde(default)]
+    pub(crate) throwing: bool,
+    #[serde(default)]
+    pub(crate) throw_break_window: u32,
 
     pub(crate) last_update: u128,
 }
@@ -24,60 +56,382 @@ pub struct PlayerInfo {
 }
 
 impl PlayerState {
-    pub fn new<M: MemoryModel>(memory: &M, player: Player) -> Self {
-        let xyz: (f32, f32, f32) = memory.xyz(player).unwrap().into();
-
-        Self { 
+    /// State with every field zeroed, as before the first read
+    pub fn blank(player: Player, character: Character) -> Self {
+        Self {
             player,
-            character: memory.character(player).unwrap(),
-
-            x: xyz.0,
-            y: xyz.1,
-            z: xyz.2,
-
-            input_attack: memory.inputted_attack(player).unwrap().into(),
-            input_direction: memory.inputted_direction(player).unwrap().into(),
-            damage_received: memory.damage_received(player).unwrap().into(),
-            facing: match memory.facing(player).unwrap() {
-                Facing::Left => 0,
-                Facing::Right => 1
-            },
+            character,
+
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
 
-            last_update: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
+            input_attack: 0,
+            input_direction: 0,
+            damage_received: 0,
+            facing: 0,
+            ground_state: None,
+            wakeup: None,
+
+            move_id: 0,
+            move_frame: 0,
+            recovery_remaining: 0,
+            attack_flags: 0,
+            active_start: 0,
+            active_end: 0,
+            stance_flags: 0,
+
+            rage: false,
+            combo_count: 0,
+            throwing: false,
+            throw_break_window: 0,
+
+            last_update: 0
         }
     }
 
-    pub fn update<M: MemoryModel>(&mut self, memory: &M) {
-        let xyz: (f32, f32, f32) = memory.xyz(self.player).unwrap().into();
+    /// Reads the player's memory in one block and decodes every field from
+    /// it. Facings aren't part of the block, so both players' are passed in.
+    pub fn update<M: MemoryModel>(
+        &mut self,
+        memory: &M,
+        facing: Facing,
+        opponent_facing: Facing
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let block = memory.player_block(self.player)?;
+        let player = self.player;
+        let previous_attack = self.input_attack;
+        let was_grounded = self.ground_state.is_some();
 
-        self.x = xyz.0;
-        self.y = xyz.1;
-        self.z = xyz.2;
+        self.character = block
+            .read::<LittleEndian, u16>(memory.player_character_id_address(player))
+            .map(|c| num::FromPrimitive::from_u16(c).unwrap_or(Character::NotSelected))?;
 
-        self.input_attack = memory.inputted_attack(self.player).unwrap().into();
-        self.input_direction = memory.inputted_direction(self.player).unwrap().into();
-        self.damage_received = memory.damage_received(self.player).unwrap().into();
-        self.facing = match memory.facing(self.player).unwrap() {
+        self.x = block.read::<LittleEndian, f32>(memory.player_coordinate_x(player))?;
+        self.y = block.read::<LittleEndian, f32>(memory.player_coordinate_y(player))?;
+        self.z = block.read::<LittleEndian, f32>(memory.player_coordinate_z(player))?;
+
+        self.input_attack = block.read::<LittleEndian, u16>(memory.input_attack_address(player))?;
+        self.input_direction = block.read::<LittleEndian, u16>(memory.input_direction_address(player))?;
+        self.damage_received = block.read::<LittleEndian, u32>(memory.player_health_address(player))?;
+        self.facing = match facing {
             Facing::Left => 0,
             Facing::Right => 1
         };
 
+        // Optional fields are left at zero when the offsets don't include them
+        let optional_field = |offset: Option<usize>| offset.map_or(Ok(0), |offset| block.read::<LittleEndian, u32>(offset));
+        self.move_id = optional_field(memory.player_move_id_address(player))?;
+        self.move_frame = optional_field(memory.player_move_frame_address(player))?;
+        self.recovery_remaining = optional_field(memory.player_move_length_address(player))?.saturating_sub(self.move_frame);
+        self.attack_flags = optional_field(memory.player_attack_flags_address(player))?;
+        self.active_start = optional_field(memory.player_active_start_address(player))?;
+        self.active_end = optional_field(memory.player_active_end_address(player))?;
+        self.stance_flags = optional_field(memory.player_stance_flags_address(player))?;
+        self.rage = optional_field(memory.player_rage_address(player))? != 0;
+        self.combo_count = optional_field(memory.player_combo_count_address(player))?;
+        self.throwing = optional_field(memory.player_throwing_address(player))? != 0;
+
+        self.ground_state = memory.ground_state(&block, player, facing, opponent_facing)?;
+        self.wakeup = match (self.ground_state, was_grounded) {
+            (Some(_), _) => self.next_wakeup(previous_attack, !was_grounded),
+            // Keeps the wakeup on the frame the player leaves the ground
+            (None, true) => self.wakeup,
+            (None, false) => None,
+        };
+
         self.last_update = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis()
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+
+        Ok(())
+    }
+
+    pub fn player(&self) -> Player {
+        self.player
     }
 
     pub fn character(&self) -> Character {
         self.character
     }
+
+    pub fn x(&self) -> f32 {
+        self.x
+    }
+
+    pub fn y(&self) -> f32 {
+        self.y
+    }
+
+    pub fn z(&self) -> f32 {
+        self.z
+    }
+
+    pub fn input_attack(&self) -> u16 {
+        self.input_attack
+    }
+
+    pub fn input_direction(&self) -> u16 {
+        self.input_direction
+    }
+
+    /// Stored as 0 when facing left and 1 when facing right
+    pub fn facing(&self) -> Facing {
+        match self.facing {
+            0 => Facing::Left,
+            _ => Facing::Right,
+        }
+    }
+
+    pub fn damage_received(&self) -> u32 {
+        self.damage_received
+    }
+
+    /// Health left, derived from the damage received this round
+    pub fn health(&self) -> u32 {
+        MAX_HEALTH.saturating_sub(self.damage_received)
+    }
+
+    /// ID of the move being performed
+    pub fn move_id(&self) -> u32 {
+        self.move_id
+    }
+
+    /// Frames elapsed since the current move started
+    pub fn move_frame(&self) -> u32 {
+        self.move_frame
+    }
+
+    /// Frames left until the current move ends and the player can act again
+    pub fn recovery_remaining(&self) -> u32 {
+        self.recovery_remaining
+    }
+
+    pub fn attack_flags(&self) -> u32 {
+        self.attack_flags
+    }
+
+    pub fn stance_flags(&self) -> u32 {
+        self.stance_flags
+    }
+
+    /// Whether the current move is an attack
+    pub fn is_attacking(&self) -> bool {
+        self.attack_flags != 0
+    }
+
+    /// First and last frame of the current move that can hit
+    pub fn active_frames(&self) -> (u32, u32) {
+        (self.active_start, self.active_end)
+    }
+
+    /// Part of the current attack the player is in. `None` when not
+    /// attacking, or when the offsets don't include the active frames.
+    pub fn attack_phase(&self) -> Option<AttackPhase> {
+        if !self.is_attacking() || self.active_end == 0 {
+            return None;
+        }
+
+        Some(if self.move_frame < self.active_start {
+            AttackPhase::Startup
+        } else if self.move_frame <= self.active_end {
+            AttackPhase::Active
+        } else {
+            AttackPhase::Recovery
+        })
+    }
+
+    /// Whether the player can still use their rage art or rage drive
+    pub fn rage(&self) -> bool {
+        self.rage
+    }
+
+    /// Hits taken in the combo the player is caught in. Counted on the player
+    /// being hit, so the attacker's combo shows up on the opponent
+    pub fn combo_count(&self) -> u32 {
+        self.combo_count
+    }
+
+    /// Whether the player is throwing the opponent
+    pub fn throwing(&self) -> bool {
+        self.throwing
+    }
+
+    /// Frames left to break the throw the player is caught in, 0 when not
+    /// being thrown. Set along with the opponent, see
+    /// [`RoundState::update_players`](crate::states::game_state::RoundState::update_players)
+    pub fn throw_break_window(&self) -> u32 {
+        self.throw_break_window
+    }
+
+    /// How the player lies on the ground, `None` while off the ground
+    pub fn ground_state(&self) -> Option<GroundState> {
+        self.ground_state
+    }
+
+    /// Way of getting up picked while on the ground, kept up to the frame
+    /// the player leaves the ground
+    pub fn wakeup(&self) -> Option<Wakeup> {
+        self.wakeup
+    }
+
+    /// Wakeup picked so far after this frame's inputs, given the buttons
+    /// held on the previous frame. Buttons held on the frame the player
+    /// lands are a tech roll; otherwise the first input picks the wakeup
+    /// and later inputs refine it, e.g. an attack out of a forward roll.
+    fn next_wakeup(&self, previous_attack: u16, landed: bool) -> Option<Wakeup> {
+        let button = InputButton::try_from((self.input_attack & !previous_attack) as usize).unwrap_or(InputButton::None);
+        let direction = InputDirection::try_from(self.input_direction as usize).unwrap_or(InputDirection::Neutral);
+        let down = matches!(direction, InputDirection::Down | InputDirection::DownBack | InputDirection::DownForward);
+
+        if landed {
+            return match self.input_attack {
+                0 => None,
+                _ => Some(Wakeup::Ukemi),
+            };
+        }
+
+        let wakeup = match (self.wakeup, button) {
+            (None, InputButton::None) => match direction {
+                InputDirection::Neutral => None,
+                InputDirection::Back => Some(Wakeup::QuickBackRoll),
+                InputDirection::Forward => Some(Wakeup::RollForward),
+                _ if down => Some(Wakeup::Crouching),
+                _ => Some(Wakeup::Standing),
+            },
+            (None, InputButton::One) if down => Some(Wakeup::RollLeftCrouching),
+            (None, InputButton::One) => Some(Wakeup::RollLeftStanding),
+            (None, InputButton::Two) if down => Some(Wakeup::RollRightCrouching),
+            (None, InputButton::Two) => Some(Wakeup::RollRightStanding),
+            (None, InputButton::Three) => Some(Wakeup::LowAttack),
+            (None, InputButton::Four) => Some(Wakeup::MidAttack),
+            (None, InputButton::ThreePlusFour) => Some(Wakeup::SpringKick),
+            (None, _) => Some(Wakeup::Special),
+
+            (Some(Wakeup::RollForward), InputButton::Three) => Some(Wakeup::RollForwardLowAttack),
+            (Some(Wakeup::RollForward), InputButton::Four) => Some(Wakeup::RollForwardMidAttack),
+            (Some(Wakeup::RollForward), InputButton::ThreePlusFour) => Some(Wakeup::RollForwardSpringAttack),
+            (Some(Wakeup::RollForward), InputButton::None) if down => Some(Wakeup::RollForwardCrouching),
+            (Some(Wakeup::RollForward), InputButton::None) if direction == InputDirection::Up => {
+                Some(Wakeup::RollForwardStanding)
+            }
+
+            (Some(Wakeup::Crouching), InputButton::Three) | (Some(Wakeup::Crouching), InputButton::Four) => {
+                Some(Wakeup::WakeupKick)
+            }
+
+            // Pressing the roll button again extends the roll
+            (Some(Wakeup::RollLeftStanding), InputButton::One) => Some(Wakeup::ExtendedRollLeftStanding),
+            (Some(Wakeup::RollLeftCrouching), InputButton::One) => Some(Wakeup::ExtendedRollLeftCrouching),
+            (Some(Wakeup::RollRightStanding), InputButton::Two) => Some(Wakeup::ExtendedRollRightStanding),
+            (Some(Wakeup::RollRightCrouching), InputButton::Two) => Some(Wakeup::ExtendedRollRightCrouching),
+
+            (wakeup, _) => wakeup,
+        };
+
+        // Holding down at any point of a roll gets up crouching
+        match wakeup {
+            Some(Wakeup::RollLeftStanding) if down => Some(Wakeup::RollLeftCrouching),
+            Some(Wakeup::RollRightStanding) if down => Some(Wakeup::RollRightCrouching),
+            Some(Wakeup::ExtendedRollLeftStanding) if down => Some(Wakeup::ExtendedRollLeftCrouching),
+            Some(Wakeup::ExtendedRollRightStanding) if down => Some(Wakeup::ExtendedRollRightCrouching),
+            wakeup => wakeup,
+        }
+    }
 }
 
 impl PlayerInfo {
-    fn screen_name(&self) -> &Option<String> {
+    pub fn screen_name(&self) -> &Option<String> {
         &self.screen_name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use InputButton as B;
+    use InputDirection as D;
+
+    /// Nothing pressed
+    const N: (D, B) = (D::Neutral, B::None);
+
+    /// Wakeup picked after each frame of `inputs` spent on the ground, the
+    /// first one being the frame the player lands
+    fn wakeup(inputs: &[(D, B)]) -> Option<Wakeup> {
+        let mut state = PlayerState::blank(Player::One, Character::Law);
+
+        for (index, (direction, button)) in inputs.iter().enumerate() {
+            let previous_attack = state.input_attack;
+            state.input_direction = *direction as u16;
+            state.input_attack = *button as u16;
+            state.wakeup = state.next_wakeup(previous_attack, index == 0);
+        }
+
+        state.wakeup
+    }
+
+    #[test]
+    fn lying_still_picks_nothing() {
+        assert_eq!(wakeup(&[N, N, N]), None);
+    }
+
+    #[test]
+    fn buttons_held_on_landing_are_a_tech_roll() {
+        assert_eq!(wakeup(&[(D::Neutral, B::One)]), Some(Wakeup::Ukemi));
+        // Pressed after landing they roll instead
+        assert_eq!(wakeup(&[N, (D::Neutral, B::One)]), Some(Wakeup::RollLeftStanding));
+    }
+
+    #[test]
+    fn directions_get_up() {
+        assert_eq!(wakeup(&[N, (D::Up, B::None)]), Some(Wakeup::Standing));
+        assert_eq!(wakeup(&[N, (D::DownBack, B::None)]), Some(Wakeup::Crouching));
+        assert_eq!(wakeup(&[N, (D::Back, B::None)]), Some(Wakeup::QuickBackRoll));
+        assert_eq!(wakeup(&[N, (D::Forward, B::None)]), Some(Wakeup::RollForward));
+    }
+
+    #[test]
+    fn one_and_two_roll_to_the_side() {
+        assert_eq!(wakeup(&[N, (D::Neutral, B::One)]), Some(Wakeup::RollLeftStanding));
+        assert_eq!(wakeup(&[N, (D::Down, B::One)]), Some(Wakeup::RollLeftCrouching));
+        assert_eq!(wakeup(&[N, (D::Neutral, B::Two)]), Some(Wakeup::RollRightStanding));
+        // Holding down later in the roll still gets up crouching
+        assert_eq!(wakeup(&[N, (D::Neutral, B::Two), (D::Down, B::None)]), Some(Wakeup::RollRightCrouching));
+    }
+
+    #[test]
+    fn pressing_the_roll_button_again_extends_the_roll() {
+        let left = [N, (D::Neutral, B::One), N, (D::Neutral, B::One)];
+        let right = [N, (D::Neutral, B::Two), N, (D::Neutral, B::Two)];
+
+        assert_eq!(wakeup(&left), Some(Wakeup::ExtendedRollLeftStanding));
+        assert_eq!(wakeup(&right), Some(Wakeup::ExtendedRollRightStanding));
+        assert_eq!(wakeup(&[&left[..], &[(D::Down, B::None)]].concat()), Some(Wakeup::ExtendedRollLeftCrouching));
+        assert_eq!(wakeup(&[&right[..], &[(D::DownForward, B::None)]].concat()), Some(Wakeup::ExtendedRollRightCrouching));
+
+        // Holding the button isn't pressing it again
+        assert_eq!(wakeup(&[N, (D::Neutral, B::One), (D::Neutral, B::One)]), Some(Wakeup::RollLeftStanding));
+    }
+
+    #[test]
+    fn kicks_attack_from_the_ground() {
+        assert_eq!(wakeup(&[N, (D::Neutral, B::Three)]), Some(Wakeup::LowAttack));
+        assert_eq!(wakeup(&[N, (D::Neutral, B::Four)]), Some(Wakeup::MidAttack));
+        assert_eq!(wakeup(&[N, (D::Neutral, B::ThreePlusFour)]), Some(Wakeup::SpringKick));
+        assert_eq!(wakeup(&[N, (D::Down, B::None), (D::Down, B::Four)]), Some(Wakeup::WakeupKick));
+        assert_eq!(wakeup(&[N, (D::Neutral, B::OnePlusTwo)]), Some(Wakeup::Special));
+    }
+
+    #[test]
+    fn forward_rolls_end_in_their_own_wakeups() {
+        let roll = |then: (D, B)| wakeup(&[N, (D::Forward, B::None), then]);
+
+        assert_eq!(roll((D::Neutral, B::Three)), Some(Wakeup::RollForwardLowAttack));
+        assert_eq!(roll((D::Neutral, B::Four)), Some(Wakeup::RollForwardMidAttack));
+        assert_eq!(roll((D::Neutral, B::ThreePlusFour)), Some(Wakeup::RollForwardSpringAttack));
+        assert_eq!(roll((D::Down, B::None)), Some(Wakeup::RollForwardCrouching));
+        assert_eq!(roll((D::Up, B::None)), Some(Wakeup::RollForwardStanding));
+    }
+}
@@ -1,3 +1,5 @@
+use std::thread;
+
 use read_process_memory::*;
 use sysinfo;
 use sysinfo::{ProcessExt, SystemExt};
@@ -16,5 +18,23 @@ pub fn pid() -> Result<Pid, &'static str> {
         }
     }
 
+    // Under Proton/Wine the process name is the Wine loader, not the game
+    #[cfg(target_os = "linux")]
+    let tekken_pid = tekken_pid.or_else(|| {
+        crate::memory::backends::linux::find_pid(crate::EXECUTABLE_NAME)
+    });
+
     tekken_pid.ok_or("Tekken is not running. Please launch Tekken then run the application again")
 }
+
+/// Polls for the Tekken 7 process every 10 seconds until it is running
+pub fn wait_for_pid() -> Pid {
+    loop {
+        match pid() {
+            Ok(pid) => return pid,
+            Err(e) => println!("Failed to acquire process ID: {:?} - Retrying in 10s...", e),
+        };
+
+        thread::sleep(std::time::Duration::from_secs(10));
+    }
+}